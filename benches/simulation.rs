@@ -0,0 +1,45 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use nand2tetris_hdl_parser::{Program, Project, Simulator};
+
+fn criterion_benchmark(c: &mut Criterion) {
+    let project = Project::new();
+    let alu = project.chip("ALU").unwrap();
+    let vectors: Vec<Vec<u64>> = (0..4096u64)
+        .map(|i| {
+            vec![
+                i * 7919,
+                i * 104729,
+                i,
+                i >> 1,
+                i >> 2,
+                i >> 3,
+                i >> 4,
+                i >> 5,
+            ]
+        })
+        .collect();
+
+    let mut simulator = Simulator::new(alu, &project).unwrap();
+    c.bench_function("Simulator ALU 4096 vectors", |b| {
+        b.iter(|| {
+            for vector in &vectors {
+                for (name, &value) in ["x", "y", "zx", "nx", "zy", "ny", "f", "no"]
+                    .iter()
+                    .zip(vector)
+                {
+                    simulator.set(name, value).unwrap();
+                }
+                simulator.eval();
+                let _ = black_box(simulator.get("out").unwrap());
+            }
+        })
+    });
+
+    let program = Program::new(alu, &project).unwrap();
+    c.bench_function("Program ALU 4096 vectors", |b| {
+        b.iter(|| program.run(black_box(&vectors)).unwrap())
+    });
+}
+
+criterion_group!(benches, criterion_benchmark);
+criterion_main!(benches);
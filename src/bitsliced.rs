@@ -0,0 +1,181 @@
+//! Bit-sliced simulation of combinational chips
+//!
+//! A [Program] is a flattened chip compiled to a straight-line list of Nand operations over `u64` words.  Bit `l`
+//! of every word belongs to lane `l`, so each pass through the program evaluates 64 independent input vectors at
+//! once.
+
+use crate::flatten::{flatten, Bus, Net, Netlist, Primitive, TRUE};
+use crate::simulator::SimulationError;
+use crate::{Chip, Project};
+
+/// Number of input vectors evaluated by a single pass through a [Program]
+pub const LANES: usize = 64;
+
+/// A type that evaluates a combinational chip on many input vectors at once
+#[derive(Debug, Clone)]
+pub struct Program {
+    inputs: Vec<(String, Vec<Net>)>,
+    outputs: Vec<(String, Vec<Net>)>,
+    ops: Vec<[Net; 3]>,
+    nets: usize,
+}
+
+impl Program {
+    /// Flatten and compile a chip, resolving its parts against a project
+    pub fn new(chip: &Chip, project: &Project) -> Result<Program, SimulationError> {
+        Program::compile(&flatten(chip, project)?)
+    }
+
+    /// Compile a flattened chip
+    ///
+    /// Fails if the chip contains clocked parts or memories, since their state cannot be shared between lanes.
+    pub fn compile(netlist: &Netlist) -> Result<Program, SimulationError> {
+        let mut ops = Vec::with_capacity(netlist.cells.len());
+        for index in netlist.topological_order()? {
+            let cell = &netlist.cells[index];
+            if cell.primitive != Primitive::Nand {
+                return Err(SimulationError::NotCombinational(format!(
+                    "{} is a {}",
+                    cell.path,
+                    cell.primitive.name()
+                )));
+            }
+            ops.push([cell.inputs[0][0], cell.inputs[1][0], cell.outputs[0][0]]);
+        }
+        let buses = |buses: &[Bus]| {
+            buses
+                .iter()
+                .map(|bus| (bus.name.clone(), bus.nets.clone()))
+                .collect()
+        };
+        Ok(Program {
+            inputs: buses(&netlist.inputs),
+            outputs: buses(&netlist.outputs),
+            ops,
+            nets: netlist.nets,
+        })
+    }
+
+    /// Names of the input buses, in the order input vectors list their values
+    pub fn input_names(&self) -> Vec<&str> {
+        self.inputs.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Names of the output buses, in the order output vectors list their values
+    pub fn output_names(&self) -> Vec<&str> {
+        self.outputs.iter().map(|(name, _)| name.as_str()).collect()
+    }
+
+    /// Number of Nand operations executed per pass
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    /// Whether the program has no operations at all
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Evaluate every input vector and return the matching output vectors
+    ///
+    /// Each input vector holds one value per input bus, ordered as [Program::input_names]; each output vector
+    /// holds one value per output bus, ordered as [Program::output_names].
+    pub fn run(&self, vectors: &[Vec<u64>]) -> Result<Vec<Vec<u64>>, SimulationError> {
+        if let Some(vector) = vectors.iter().find(|v| v.len() != self.inputs.len()) {
+            return Err(SimulationError::InvalidVector(format!(
+                "expected {} input values but a vector has {}",
+                self.inputs.len(),
+                vector.len()
+            )));
+        }
+
+        let mut words = vec![0u64; self.nets];
+        let mut results = Vec::with_capacity(vectors.len());
+        for batch in vectors.chunks(LANES) {
+            for word in words.iter_mut() {
+                *word = 0;
+            }
+            words[TRUE] = !0;
+            for (index, (_, nets)) in self.inputs.iter().enumerate() {
+                for (bit, &net) in nets.iter().enumerate().take(64) {
+                    words[net] = batch.iter().enumerate().fold(0, |word, (lane, vector)| {
+                        word | (vector[index] >> bit & 1) << lane
+                    });
+                }
+            }
+
+            for &[a, b, out] in &self.ops {
+                words[out] = !(words[a] & words[b]);
+            }
+
+            for lane in 0..batch.len() {
+                results.push(
+                    self.outputs
+                        .iter()
+                        .map(|(_, nets)| {
+                            nets.iter()
+                                .take(64)
+                                .enumerate()
+                                .fold(0, |value, (bit, &net)| {
+                                    value | (words[net] >> lane & 1) << bit
+                                })
+                        })
+                        .collect(),
+                );
+            }
+        }
+        Ok(results)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::bitsliced::Program;
+    use crate::simulator::{SimulationError, Simulator};
+    use crate::Project;
+
+    #[test]
+    fn matches_scalar_simulation() {
+        let project = Project::new();
+        let chip = project.chip("ALU").unwrap();
+        let program = Program::new(chip, &project).unwrap();
+        let mut simulator = Simulator::new(chip, &project).unwrap();
+
+        let mut seed = 0x2545f4914f6cdd1du64;
+        let vectors: Vec<Vec<u64>> = (0..200)
+            .map(|_| {
+                program
+                    .input_names()
+                    .iter()
+                    .map(|_| {
+                        seed ^= seed << 13;
+                        seed ^= seed >> 7;
+                        seed ^= seed << 17;
+                        seed
+                    })
+                    .collect()
+            })
+            .collect();
+        let outputs = program.run(&vectors).unwrap();
+        assert_eq!(outputs.len(), vectors.len());
+
+        for (vector, output) in vectors.iter().zip(&outputs) {
+            for (name, &value) in program.input_names().iter().zip(vector) {
+                simulator.set(name, value).unwrap();
+            }
+            simulator.eval();
+            for (name, &value) in program.output_names().iter().zip(output) {
+                assert_eq!(simulator.get(name).unwrap(), value);
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_sequential_chips() {
+        let project = Project::new();
+        assert!(matches!(
+            Program::new(project.chip("Bit").unwrap(), &project),
+            Err(SimulationError::NotCombinational(_))
+        ));
+    }
+}
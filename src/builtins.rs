@@ -0,0 +1,87 @@
+//! HDL implementations of the chips the official simulator provides as builtins
+//!
+//! Parts that a project does not implement itself fall back to these definitions, so a project 5
+//! directory containing only `CPU.hdl` can still be flattened down to primitives.
+
+use crate::{parse_hdl, Chip};
+
+/// Builtin chip names and their HDL source, ordered the same way the course introduces them
+const LIBRARY: &[(&str, &str)] = &[
+    ("Not", include_str!("builtins/Not.hdl")),
+    ("And", include_str!("builtins/And.hdl")),
+    ("Or", include_str!("builtins/Or.hdl")),
+    ("Xor", include_str!("builtins/Xor.hdl")),
+    ("Mux", include_str!("builtins/Mux.hdl")),
+    ("DMux", include_str!("builtins/DMux.hdl")),
+    ("Not16", include_str!("builtins/Not16.hdl")),
+    ("And16", include_str!("builtins/And16.hdl")),
+    ("Or16", include_str!("builtins/Or16.hdl")),
+    ("Mux16", include_str!("builtins/Mux16.hdl")),
+    ("Or8Way", include_str!("builtins/Or8Way.hdl")),
+    ("Mux4Way16", include_str!("builtins/Mux4Way16.hdl")),
+    ("Mux8Way16", include_str!("builtins/Mux8Way16.hdl")),
+    ("DMux4Way", include_str!("builtins/DMux4Way.hdl")),
+    ("DMux8Way", include_str!("builtins/DMux8Way.hdl")),
+    ("HalfAdder", include_str!("builtins/HalfAdder.hdl")),
+    ("FullAdder", include_str!("builtins/FullAdder.hdl")),
+    ("Add16", include_str!("builtins/Add16.hdl")),
+    ("Inc16", include_str!("builtins/Inc16.hdl")),
+    ("ALU", include_str!("builtins/ALU.hdl")),
+    ("Bit", include_str!("builtins/Bit.hdl")),
+    ("Register", include_str!("builtins/Register.hdl")),
+    ("ARegister", include_str!("builtins/ARegister.hdl")),
+    ("DRegister", include_str!("builtins/DRegister.hdl")),
+    ("PC", include_str!("builtins/PC.hdl")),
+    ("RAM8", include_str!("builtins/RAM8.hdl")),
+    ("RAM64", include_str!("builtins/RAM64.hdl")),
+    ("RAM512", include_str!("builtins/RAM512.hdl")),
+    ("RAM4K", include_str!("builtins/RAM4K.hdl")),
+    ("Memory", include_str!("builtins/Memory.hdl")),
+    ("CPU", include_str!("builtins/CPU.hdl")),
+    ("Computer", include_str!("builtins/Computer.hdl")),
+];
+
+/// Parse every builtin chip in the library
+pub(crate) fn library() -> Vec<Chip> {
+    LIBRARY
+        .iter()
+        .map(|(name, hdl)| {
+            parse_hdl(hdl).unwrap_or_else(|e| panic!("builtin chip {} does not parse: {}", name, e))
+        })
+        .collect()
+}
+
+/// HDL source of a builtin chip
+pub(crate) fn source(name: &str) -> Option<&'static str> {
+    LIBRARY
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, hdl)| *hdl)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{library, LIBRARY};
+    use crate::{flatten, Project};
+
+    #[test]
+    fn library_parses() {
+        let chips = library();
+        assert_eq!(chips.len(), LIBRARY.len());
+        for (chip, (name, _)) in chips.iter().zip(LIBRARY) {
+            assert_eq!(&chip.name, name);
+        }
+    }
+
+    #[test]
+    fn library_flattens() {
+        let project = Project::new();
+        // the larger memories are left out to keep debug test runs quick
+        for chip in library()
+            .iter()
+            .filter(|chip| !["RAM512", "RAM4K"].contains(&chip.name.as_str()))
+        {
+            assert!(flatten(chip, &project).is_ok(), "{}", chip.name);
+        }
+    }
+}
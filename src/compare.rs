@@ -0,0 +1,393 @@
+//! Comparison of output files against comparison files
+//!
+//! `.out` and `.cmp` files are tables of cells between `|` characters, starting with a header naming each column.
+//! As in the official simulator, lines are compared character for character, except that `*` in the comparison
+//! file matches any character.
+
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+/// A row of a table, as written
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Row {
+    /// Holds the line of the file, counted from one, the row was read from
+    pub line: usize,
+    /// Holds the text between each pair of `|`, padding included
+    pub cells: Vec<String>,
+}
+
+/// A type that represents a parsed `.out` or `.cmp` file
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Table {
+    /// Holds the header row naming each column
+    pub header: Row,
+    /// Holds the rows of values in order
+    pub rows: Vec<Row>,
+}
+
+/// Error returned when a file is not a table
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TableError {
+    /// Holds the line, counted from one, that is not a table row
+    pub line: usize,
+    /// Holds what is wrong with it
+    pub message: String,
+}
+
+impl fmt::Display for TableError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for TableError {}
+
+impl Table {
+    /// Parse a table, ignoring blank lines and line endings
+    pub fn parse(text: &str) -> Result<Table, TableError> {
+        let mut rows = vec![];
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.trim().is_empty() {
+                continue;
+            }
+            let error = |message: &str| TableError {
+                line: index + 1,
+                message: message.to_string(),
+            };
+            let inner = line
+                .strip_prefix('|')
+                .and_then(|line| line.strip_suffix('|'))
+                .ok_or_else(|| error("expected a row between \"|\" characters"))?;
+            let cells: Vec<String> = inner.split('|').map(str::to_string).collect();
+            let expected = rows.first().map(|header: &Row| header.cells.len());
+            if let Some(expected) = expected.filter(|&expected| expected != cells.len()) {
+                return Err(error(&format!(
+                    "expected {} cells, found {}",
+                    expected,
+                    cells.len()
+                )));
+            }
+            rows.push(Row {
+                line: index + 1,
+                cells,
+            });
+        }
+        if rows.is_empty() {
+            return Err(TableError {
+                line: 1,
+                message: "expected a header".to_string(),
+            });
+        }
+        let header = rows.remove(0);
+        Ok(Table { header, rows })
+    }
+
+    /// Names of the columns, without padding
+    pub fn columns(&self) -> Vec<&str> {
+        self.header.cells.iter().map(|cell| cell.trim()).collect()
+    }
+
+    /// The header followed by every row
+    fn lines(&self) -> impl Iterator<Item = &Row> {
+        std::iter::once(&self.header).chain(&self.rows)
+    }
+}
+
+/// A cell, or a whole line, of an output file that differs from the comparison file
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Mismatch {
+    /// Holds the line, counted from one, at which the files differ
+    pub line: usize,
+    /// Holds the character column, counted from one, at which the differing cell starts
+    pub column: usize,
+    /// Holds the name of the column of the cell, or `None` if a whole line is missing or extra
+    pub name: Option<String>,
+    /// Holds the cell as the comparison file has it, padding included
+    pub expected: String,
+    /// Holds the cell as the output file has it, padding included
+    pub found: String,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "comparison failure at line {}, column {}",
+            self.line, self.column
+        )?;
+        if let Some(name) = &self.name {
+            write!(f, " ({})", name)?;
+        }
+        write!(
+            f,
+            ": expected \"{}\", found \"{}\"",
+            self.expected, self.found
+        )
+    }
+}
+
+/// Whether an output cell matches a comparison cell, where `*` matches any character
+fn matches(found: &str, expected: &str) -> bool {
+    found.chars().count() == expected.chars().count()
+        && found
+            .chars()
+            .zip(expected.chars())
+            .all(|(found, expected)| expected == '*' || found == expected)
+}
+
+/// Mismatches between two rows at the same position, one per differing cell
+fn row_mismatches(found: &Row, expected: &Row, columns: &[&str]) -> Vec<Mismatch> {
+    let mut mismatches = vec![];
+    let mut column = 2;
+    let cells = found.cells.len().max(expected.cells.len());
+    for index in 0..cells {
+        let found_cell = found.cells.get(index).map_or("", String::as_str);
+        let expected_cell = expected.cells.get(index).map_or("", String::as_str);
+        if !matches(found_cell, expected_cell) {
+            mismatches.push(Mismatch {
+                line: expected.line,
+                column,
+                name: columns.get(index).map(|name| name.to_string()),
+                expected: expected_cell.to_string(),
+                found: found_cell.to_string(),
+            });
+        }
+        column += expected_cell.chars().count() + 1;
+    }
+    mismatches
+}
+
+/// A whole row only one of the tables has
+fn row_missing(row: Option<&Row>, other: Option<&Row>) -> Mismatch {
+    let text =
+        |row: Option<&Row>| row.map_or(String::new(), |row| format!("|{}|", row.cells.join("|")));
+    Mismatch {
+        line: row.or(other).map_or(0, |row| row.line),
+        column: 1,
+        name: None,
+        expected: text(other),
+        found: text(row),
+    }
+}
+
+/// The first difference between an output table and a comparison table, as the official simulator reports it
+///
+/// Lines are compared in order, the header included, and the first differing cell of the first differing line is
+/// returned.  An output that stops early or goes on longer differs at the first line the other file lacks.
+pub fn compare(output: &Table, expected: &Table) -> Option<Mismatch> {
+    let columns = expected.columns();
+    let mut found = output.lines();
+    let mut wanted = expected.lines();
+    loop {
+        match (found.next(), wanted.next()) {
+            (None, None) => return None,
+            (Some(row), Some(expected)) => {
+                if let Some(mismatch) = row_mismatches(row, expected, &columns).into_iter().next() {
+                    return Some(mismatch);
+                }
+            }
+            (row, expected) => return Some(row_missing(row, expected)),
+        }
+    }
+}
+
+/// A line of a [TableDiff]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum DiffLine {
+    /// A line both files have
+    Same(Row),
+    /// A line that differs, with every differing cell
+    Changed {
+        /// Holds the line of the output file
+        found: Row,
+        /// Holds the line of the comparison file
+        expected: Row,
+        /// Holds the differing cells
+        mismatches: Vec<Mismatch>,
+    },
+    /// A line only the comparison file has
+    Missing(Row),
+    /// A line only the output file has
+    Extra(Row),
+}
+
+/// Every difference between an output table and a comparison table
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TableDiff {
+    /// Holds every line of either table in order
+    pub lines: Vec<DiffLine>,
+}
+
+impl TableDiff {
+    /// Every differing cell, and every missing or extra line, in order
+    pub fn mismatches(&self) -> Vec<Mismatch> {
+        self.lines
+            .iter()
+            .flat_map(|line| match line {
+                DiffLine::Same(_) => vec![],
+                DiffLine::Changed { mismatches, .. } => mismatches.clone(),
+                DiffLine::Missing(row) => vec![row_missing(None, Some(row))],
+                DiffLine::Extra(row) => vec![row_missing(Some(row), None)],
+            })
+            .collect()
+    }
+
+    /// Whether the tables match
+    pub fn is_empty(&self) -> bool {
+        self.lines
+            .iter()
+            .all(|line| matches!(line, DiffLine::Same(_)))
+    }
+}
+
+impl fmt::Display for TableDiff {
+    /// The table with differing lines shown as output (`-`) and comparison (`+`), and `^` under differing cells
+    /// followed by the names of their columns
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = |row: &Row| format!("|{}|", row.cells.join("|"));
+        for line in &self.lines {
+            match line {
+                DiffLine::Same(row) => writeln!(f, "  {}", text(row))?,
+                DiffLine::Changed {
+                    found,
+                    expected,
+                    mismatches,
+                } => {
+                    writeln!(f, "- {}", text(found))?;
+                    writeln!(f, "+ {}", text(expected))?;
+                    let mut marks = String::from("  ");
+                    for mismatch in mismatches {
+                        let start = mismatch.column + 1;
+                        marks.push_str(&" ".repeat(start.saturating_sub(marks.chars().count())));
+                        let width = mismatch.expected.chars().count().max(1);
+                        marks.push_str(&"^".repeat(width));
+                    }
+                    let names: Vec<&str> = mismatches
+                        .iter()
+                        .filter_map(|mismatch| mismatch.name.as_deref())
+                        .collect();
+                    writeln!(f, "{} {}", marks, names.join(", "))?;
+                }
+                DiffLine::Missing(row) => writeln!(f, "+ {}", text(row))?,
+                DiffLine::Extra(row) => writeln!(f, "- {}", text(row))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Every difference between an output table and a comparison table, line by line
+pub fn diff_tables(output: &Table, expected: &Table) -> TableDiff {
+    let columns = expected.columns();
+    let mut found = output.lines();
+    let mut wanted = expected.lines();
+    let mut lines = vec![];
+    loop {
+        lines.push(match (found.next(), wanted.next()) {
+            (None, None) => return TableDiff { lines },
+            (Some(row), Some(expected)) => {
+                let mismatches = row_mismatches(row, expected, &columns);
+                if mismatches.is_empty() {
+                    DiffLine::Same(row.clone())
+                } else {
+                    DiffLine::Changed {
+                        found: row.clone(),
+                        expected: expected.clone(),
+                        mismatches,
+                    }
+                }
+            }
+            (Some(row), None) => DiffLine::Extra(row.clone()),
+            (None, Some(expected)) => DiffLine::Missing(expected.clone()),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compare::{compare, diff_tables, Table};
+
+    const CMP: &str = "|   a   |   b   |  out  |\r\n\
+                       |   0   |   0   |   0   |\r\n\
+                       |   0   |   1   |   1   |\r\n\
+                       |   1   |   0   |   *   |\r\n\
+                       |   1   |   1   |   0   |\r\n";
+
+    #[test]
+    fn parses_tables() {
+        let table = Table::parse(CMP).unwrap();
+        assert_eq!(table.columns(), vec!["a", "b", "out"]);
+        assert_eq!(table.rows.len(), 4);
+        assert_eq!(table.rows[1].cells[2], "   1   ");
+        assert_eq!(table.rows[1].line, 3);
+
+        let error = Table::parse("|a|b|\n|0|\n").unwrap_err();
+        assert_eq!(error.to_string(), "line 2: expected 2 cells, found 1");
+        assert!(Table::parse("a b\n").is_err());
+        assert!(Table::parse("\n").is_err());
+    }
+
+    #[test]
+    fn finds_first_mismatch() {
+        let expected = Table::parse(CMP).unwrap();
+        let output = Table::parse(
+            "|   a   |   b   |  out  |\n\
+             |   0   |   0   |   0   |\n\
+             |   0   |   1   |   1   |\n\
+             |   1   |   0   |   0   |\n\
+             |   1   |   1   |   0   |\n",
+        )
+        .unwrap();
+        assert_eq!(compare(&output, &expected), None);
+
+        let wrong = Table::parse(
+            "|   a   |   b   |  out  |\n\
+             |   0   |   0   |   0   |\n\
+             |   0   |   1   |   0   |\n",
+        )
+        .unwrap();
+        let mismatch = compare(&wrong, &expected).unwrap();
+        assert_eq!((mismatch.line, mismatch.column), (3, 18));
+        assert_eq!(mismatch.name.as_deref(), Some("out"));
+        assert_eq!(
+            mismatch.to_string(),
+            "comparison failure at line 3, column 18 (out): expected \"   1   \", found \"   0   \""
+        );
+
+        let short = Table::parse("|   a   |   b   |  out  |\n|   0   |   0   |   0   |\n").unwrap();
+        let mismatch = compare(&short, &expected).unwrap();
+        assert_eq!((mismatch.line, mismatch.name), (3, None));
+        assert_eq!(mismatch.found, "");
+    }
+
+    #[test]
+    fn diffs_tables() {
+        let expected = Table::parse(CMP).unwrap();
+        let output = Table::parse(
+            "|   a   |   b   |  out  |\n\
+             |   0   |   0   |   1   |\n\
+             |   0   |   1   |   1   |\n\
+             |   1   |   0   |   1   |\n\
+             |   1   |   0   |   1   |\n",
+        )
+        .unwrap();
+        let diff = diff_tables(&output, &expected);
+        assert!(!diff.is_empty());
+        assert_eq!(diff.mismatches().len(), 3);
+        assert_eq!(
+            diff.to_string(),
+            "  |   a   |   b   |  out  |\n\
+             - |   0   |   0   |   1   |\n\
+             + |   0   |   0   |   0   |\n\
+             \x20                  ^^^^^^^ out\n\
+             \x20 |   0   |   1   |   1   |\n\
+             \x20 |   1   |   0   |   1   |\n\
+             - |   1   |   0   |   1   |\n\
+             + |   1   |   1   |   0   |\n\
+             \x20          ^^^^^^^ ^^^^^^^ b, out\n"
+        );
+        assert!(diff_tables(&expected, &expected).is_empty());
+    }
+}
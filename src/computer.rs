@@ -0,0 +1,383 @@
+//! Running Hack programs on a simulated `Computer` chip
+//!
+//! A [Computer] simulates the `Computer` chip of a project, which is the builtin one built from the project's
+//! `CPU` and `Memory` unless the project supplies its own.  Programs are loaded into its `ROM32K` and data memory
+//! is read through the memory map, so a student's CPU can be checked by running whole programs.
+
+use crate::flatten::{flatten_with_limits, FlattenError};
+use crate::keyboard::KeyScript;
+use crate::limits::Limits;
+use crate::memory::Memory;
+use crate::simulator::{SimulationError, Simulator};
+use crate::Project;
+use dict_derive::{FromPyObject, IntoPyObject};
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// Number of instructions the instruction memory holds
+pub const ROM_SIZE: usize = 1 << 15;
+
+/// First address of the screen memory map
+pub const SCREEN: usize = 1 << 14;
+
+/// Address of the keyboard register
+pub const KEYBOARD: usize = 24576;
+
+/// Parse the text of a `.hack` file: one instruction per line written as sixteen binary digits
+///
+/// Blank lines are skipped.  Fails with [SimulationError::InvalidProgram] on any other line or if the program does
+/// not fit in the instruction memory.
+pub fn parse_hack(text: &str) -> Result<Vec<u16>, SimulationError> {
+    let mut program = vec![];
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.len() != 16 || !line.chars().all(|c| c == '0' || c == '1') {
+            return Err(SimulationError::InvalidProgram(format!(
+                "line {}: expected 16 binary digits, found {:?}",
+                number + 1,
+                line
+            )));
+        }
+        program.push(u16::from_str_radix(line, 2).unwrap());
+    }
+    if program.len() > ROM_SIZE {
+        return Err(SimulationError::InvalidProgram(format!(
+            "{} instructions do not fit in ROM32K",
+            program.len()
+        )));
+    }
+    Ok(program)
+}
+
+/// Values of the CPU registers
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    Hash,
+    Default,
+    Serialize,
+    Deserialize,
+    FromPyObject,
+    IntoPyObject,
+)]
+pub struct Registers {
+    /// Holds the address register
+    pub a: u16,
+    /// Holds the data register
+    pub d: u16,
+    /// Holds the program counter
+    pub pc: u16,
+}
+
+/// A type that runs Hack programs on a simulated `Computer` chip
+#[derive(Debug, Clone)]
+pub struct Computer {
+    simulator: Simulator,
+    /// instance path of the CPU
+    cpu: String,
+    keys: KeyScript,
+    /// key last written to the keyboard register by the script, if any
+    key: Option<u16>,
+}
+
+impl Computer {
+    /// Flatten the project's `Computer` chip and prepare it for simulation
+    pub fn new(project: &Project) -> Result<Computer, SimulationError> {
+        Computer::new_with_limits(project, &Limits::default())
+    }
+
+    /// Prepare a computer like [Computer::new], stopping with [SimulationError::LimitExceeded] if flattening its
+    /// chip goes beyond the gate, depth or time limits
+    pub fn new_with_limits(
+        project: &Project,
+        limits: &Limits,
+    ) -> Result<Computer, SimulationError> {
+        let chip = project
+            .chip("Computer")
+            .ok_or_else(|| FlattenError::new("unknown chip Computer"))?;
+        let simulator = Simulator::from_netlist(flatten_with_limits(chip, project, limits)?)?;
+        let cpu = simulator
+            .netlist()
+            .instances
+            .iter()
+            .find(|instance| instance.chip == "CPU")
+            .map(|instance| instance.path.clone())
+            .ok_or_else(|| SimulationError::UnknownPin("CPU".to_string()))?;
+        // fail now rather than on the first access if the memory map is not built from the builtins
+        for memory in &["ROM32K", "RAM16K", "Screen", "Keyboard"] {
+            let _ = simulator.memory(memory)?;
+        }
+        Ok(Computer {
+            simulator,
+            cpu,
+            keys: KeyScript::new(),
+            key: None,
+        })
+    }
+
+    /// The underlying simulator, for access to any wire of the computer
+    pub fn simulator(&self) -> &Simulator {
+        &self.simulator
+    }
+
+    /// The underlying simulator, mutably
+    pub fn simulator_mut(&mut self) -> &mut Simulator {
+        &mut self.simulator
+    }
+
+    /// Replace the contents of the instruction memory with a program
+    pub fn load(&mut self, program: &[u16]) -> Result<(), SimulationError> {
+        let rom = self.simulator.memory_mut("ROM32K")?;
+        rom.clear();
+        rom.load(program);
+        self.simulator.eval();
+        Ok(())
+    }
+
+    /// Parse the text of a `.hack` file and load it into the instruction memory
+    pub fn load_hack(&mut self, text: &str) -> Result<(), SimulationError> {
+        self.load(&parse_hack(text)?)
+    }
+
+    /// Hold `reset` for one clock cycle so execution restarts from the first instruction
+    pub fn reset(&mut self) -> Result<(), SimulationError> {
+        self.simulator.set("reset", 1)?;
+        self.simulator.step();
+        self.simulator.set("reset", 0)?;
+        self.simulator.eval();
+        Ok(())
+    }
+
+    /// Schedule keyboard input for [Computer::run], replacing any earlier script
+    ///
+    /// Cycles in the script count from the start of the simulation, as [Simulator::time] does.
+    pub fn set_keys(&mut self, keys: KeyScript) {
+        self.keys = keys;
+        self.key = None;
+    }
+
+    /// Execute one instruction per clock cycle for the given number of cycles
+    ///
+    /// Before each cycle the keyboard register holds the key scheduled by [Computer::set_keys] for that cycle,
+    /// including keys pressed at cycles that had already passed when the script was set.  Without a script the
+    /// register is left alone.  Nothing bounds the run; see [Computer::run_with_limits].
+    pub fn run(&mut self, cycles: usize) {
+        // nothing is exceeded without limits
+        let _ = self.run_with_limits(cycles, &Limits::default());
+    }
+
+    /// Run like [Computer::run], stopping with [SimulationError::LimitExceeded] once the simulation has gone beyond
+    /// the cycle limit, counted from the start of the simulation, or this call beyond the time limit
+    pub fn run_with_limits(
+        &mut self,
+        cycles: usize,
+        limits: &Limits,
+    ) -> Result<(), SimulationError> {
+        let started = Instant::now();
+        for _ in 0..cycles {
+            limits.check_time(started)?;
+            self.apply_keys();
+            self.simulator.step();
+            limits.check_cycles(self.simulator.time().cycle)?;
+        }
+        Ok(())
+    }
+
+    /// Write the key held down during the current cycle to the keyboard register if it changed
+    fn apply_keys(&mut self) {
+        if self.keys.events().is_empty() {
+            return;
+        }
+        let key = self.keys.key_at(self.simulator.time().cycle);
+        if self.key == Some(key) {
+            return;
+        }
+        // the keyboard was found when the computer was created
+        if let Ok(keyboard) = self.simulator.memory_mut("Keyboard") {
+            keyboard.poke(0, key);
+            self.simulator.eval();
+        }
+        self.key = Some(key);
+    }
+
+    /// Read a word of data memory, addressed through the memory map like the `M` register
+    ///
+    /// Addresses beyond the keyboard read as zero.
+    pub fn peek(&self, address: usize) -> Result<u16, SimulationError> {
+        let (memory, offset) = map(address);
+        Ok(self.simulator.memory(memory)?.peek(offset))
+    }
+
+    /// Write a word of data memory, addressed through the memory map like the `M` register
+    pub fn poke(&mut self, address: usize, value: u16) -> Result<(), SimulationError> {
+        let (memory, offset) = map(address);
+        self.simulator.memory_mut(memory)?.poke(offset, value);
+        self.simulator.eval();
+        Ok(())
+    }
+
+    /// Contents of the screen memory map, which [screen](crate::screen) renders as an image
+    pub fn screen(&self) -> Result<&Memory, SimulationError> {
+        self.simulator.memory("Screen")
+    }
+
+    /// Values of the address and data registers and of the program counter
+    ///
+    /// The registers are found as the `ARegister` and `DRegister` parts of the CPU, so this fails with
+    /// [SimulationError::UnknownPin] for a CPU that stores them in other chips.
+    pub fn registers(&self) -> Result<Registers, SimulationError> {
+        let register = |chip: &str| -> Result<u16, SimulationError> {
+            let prefix = format!("{}/", self.cpu);
+            let path = self
+                .simulator
+                .netlist()
+                .instances
+                .iter()
+                .find(|instance| instance.chip == chip && instance.path.starts_with(&prefix))
+                .map(|instance| format!("{}/out", instance.path))
+                .ok_or_else(|| SimulationError::UnknownPin(format!("{}{}", prefix, chip)))?;
+            Ok(self.simulator.get(&path)? as u16)
+        };
+        Ok(Registers {
+            a: register("ARegister")?,
+            d: register("DRegister")?,
+            pc: self.simulator.get(&format!("{}/pc", self.cpu))? as u16,
+        })
+    }
+}
+
+/// The memory builtin and offset within it that a data memory address refers to
+fn map(address: usize) -> (&'static str, usize) {
+    if address < SCREEN {
+        ("RAM16K", address)
+    } else if address < KEYBOARD {
+        ("Screen", address - SCREEN)
+    } else {
+        ("Keyboard", address - KEYBOARD)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::computer::{parse_hack, Computer, Registers};
+    use crate::keyboard::KeyScript;
+    use crate::limits::{Limit, Limits};
+    use crate::simulator::SimulationError;
+    use crate::Project;
+
+    const MAX: &str = include_str!("../test_cases/Max.hack");
+
+    #[test]
+    fn parses_hack() {
+        assert_eq!(
+            parse_hack("0000000000000101\n\n1110110000010000\n").unwrap(),
+            vec![5, 0xec10]
+        );
+        assert!(matches!(
+            parse_hack("0000000000000101\n@5\n"),
+            Err(SimulationError::InvalidProgram(_))
+        ));
+    }
+
+    #[test]
+    fn runs_max() {
+        let project = Project::new();
+        let mut computer = Computer::new(&project).unwrap();
+        computer.load_hack(MAX).unwrap();
+        for &(x, y) in &[(3, 5), (23456, 12345)] {
+            computer.poke(0, x).unwrap();
+            computer.poke(1, y).unwrap();
+            computer.reset().unwrap();
+            computer.run(14);
+            assert_eq!(computer.peek(2).unwrap(), x.max(y));
+        }
+        assert_eq!(
+            computer.registers().unwrap(),
+            Registers {
+                a: 14,
+                d: 23456,
+                pc: 14
+            }
+        );
+        assert_eq!(
+            computer.simulator().get("CPU/DRegister/out").unwrap(),
+            23456
+        );
+    }
+
+    #[test]
+    fn reads_scripted_keys() {
+        let project = Project::new();
+        let mut computer = Computer::new(&project).unwrap();
+        // copy the keyboard to RAM[0] forever
+        computer
+            .load(&[0x6000, 0xfc10, 0x0000, 0xe308, 0x0000, 0xea87])
+            .unwrap();
+        computer.set_keys(KeyScript::parse("10 press A\n30 release").unwrap());
+        computer.run(10);
+        assert_eq!(computer.peek(0).unwrap(), 0);
+        computer.run(10);
+        assert_eq!(computer.peek(0).unwrap(), 65);
+        assert_eq!(computer.peek(24576).unwrap(), 65);
+        computer.run(20);
+        assert_eq!(computer.peek(0).unwrap(), 0);
+    }
+
+    #[test]
+    fn applies_keys_scheduled_earlier() {
+        let project = Project::new();
+        let mut computer = Computer::new(&project).unwrap();
+        computer.run(5);
+        computer.reset().unwrap();
+        computer.set_keys(KeyScript::parse("0 press A\n3 press B").unwrap());
+        computer.run(1);
+        assert_eq!(computer.peek(24576).unwrap(), 66);
+        computer.set_keys(KeyScript::parse("2 release").unwrap());
+        computer.run(1);
+        assert_eq!(computer.peek(24576).unwrap(), 0);
+    }
+
+    #[test]
+    fn bounds_runs() {
+        let project = Project::new();
+        let limits = Limits {
+            cycles: Some(10),
+            ..Limits::default()
+        };
+        let mut computer = Computer::new_with_limits(&project, &limits).unwrap();
+        computer.load_hack(MAX).unwrap();
+        assert!(computer.run_with_limits(10, &limits).is_ok());
+        assert!(matches!(
+            computer.run_with_limits(1, &limits),
+            Err(SimulationError::LimitExceeded(Limit::Cycles(10)))
+        ));
+        let gates = Limits {
+            gates: Some(100),
+            ..Limits::default()
+        };
+        assert!(matches!(
+            Computer::new_with_limits(&project, &gates),
+            Err(SimulationError::LimitExceeded(Limit::Gates(100)))
+        ));
+    }
+
+    #[test]
+    fn maps_memory() {
+        let project = Project::new();
+        let mut computer = Computer::new(&project).unwrap();
+        computer.poke(16384 + 32, 0xffff).unwrap();
+        computer.poke(24576, 65).unwrap();
+        assert_eq!(
+            computer.simulator().memory("Screen").unwrap().peek(32),
+            0xffff
+        );
+        assert_eq!(computer.peek(24576).unwrap(), 65);
+        assert_eq!(computer.peek(30000).unwrap(), 0);
+    }
+}
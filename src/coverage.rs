@@ -0,0 +1,448 @@
+//! Toggle coverage of the wires and parts of a chip during a test
+//!
+//! A bit of a wire is covered when the test drives it from 0 to 1 and from 1 to 0 at least once, and a part is
+//! exercised when any bit connected to one of its inputs changes.  Coverage is reported per chip definition,
+//! combining every instance of the chip in the hierarchy, and points back to where each wire and part was written
+//! in the chip's HDL, so a test that never sets `sel` on any `Mux` shows up as the `sel` pin of `Mux.hdl`.
+
+use crate::flatten::{flatten_with_limits, Net, Netlist};
+use crate::limits::Limits;
+use crate::project::Resolved;
+use crate::runner::{run_simulator, TestRun};
+use crate::simulator::{SimulationError, Simulator};
+use crate::test_script::TestScript;
+use crate::{parse_hdl_spans, Chip, ChipSpans, Project, Span};
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Instant;
+
+/// Which nets of a simulated chip have risen and fallen, recorded by
+/// [Simulator::record_toggles](crate::Simulator::record_toggles)
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Toggles {
+    last: Vec<bool>,
+    rose: Vec<bool>,
+    fell: Vec<bool>,
+}
+
+impl Toggles {
+    pub(crate) fn new(values: &[bool]) -> Toggles {
+        Toggles {
+            last: values.to_vec(),
+            rose: vec![false; values.len()],
+            fell: vec![false; values.len()],
+        }
+    }
+
+    pub(crate) fn sample(&mut self, values: &[bool]) {
+        for (net, (&value, last)) in values.iter().zip(self.last.iter_mut()).enumerate() {
+            if value && !*last {
+                self.rose[net] = true;
+            } else if !value && *last {
+                self.fell[net] = true;
+            }
+            *last = value;
+        }
+    }
+
+    /// Whether a net has gone from 0 to 1
+    pub fn rose(&self, net: Net) -> bool {
+        self.rose.get(net).copied().unwrap_or(false)
+    }
+
+    /// Whether a net has gone from 1 to 0
+    pub fn fell(&self, net: Net) -> bool {
+        self.fell.get(net).copied().unwrap_or(false)
+    }
+}
+
+/// Coverage of one pin or internal wire of a chip
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct WireCoverage {
+    /// Holds the name of the wire
+    pub name: String,
+    /// Holds whether each bit, least significant first, has gone from 0 to 1
+    pub rose: Vec<bool>,
+    /// Holds whether each bit has gone from 1 to 0
+    pub fell: Vec<bool>,
+    /// Holds where the wire is declared, or first connected for internal wires, if the HDL source is known
+    pub span: Option<Span>,
+    /// Holds the line the span starts on, counted from one
+    pub line: Option<usize>,
+}
+
+impl WireCoverage {
+    /// Number of bits that toggled both ways
+    pub fn toggled_bits(&self) -> usize {
+        self.rose
+            .iter()
+            .zip(&self.fell)
+            .filter(|(&rose, &fell)| rose && fell)
+            .count()
+    }
+
+    /// Whether every bit toggled both ways
+    pub fn toggled(&self) -> bool {
+        self.toggled_bits() == self.rose.len()
+    }
+
+    /// Name of a single bit, like `sel` or `a[3]`
+    fn bit(&self, bit: usize) -> String {
+        if self.rose.len() == 1 {
+            self.name.clone()
+        } else {
+            format!("{}[{}]", self.name, bit)
+        }
+    }
+}
+
+/// Coverage of one part of a chip
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PartCoverage {
+    /// Holds the name of the part's chip
+    pub name: String,
+    /// Holds the position of the part among the chip's parts
+    pub index: usize,
+    /// Holds whether any bit connected to an input of the part changed
+    pub exercised: bool,
+    /// Holds where the part is written, if the HDL source is known
+    pub span: Option<Span>,
+    /// Holds the line the span starts on, counted from one
+    pub line: Option<usize>,
+}
+
+/// Coverage of every instance of one chip in a hierarchy
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ChipCoverage {
+    /// Holds the name of the chip
+    pub chip: String,
+    /// Holds the number of instances the coverage combines
+    pub instances: usize,
+    /// Holds the inputs, then the outputs, then the internal wires in the order parts first connect them
+    pub wires: Vec<WireCoverage>,
+    /// Holds the parts in the order they are written
+    pub parts: Vec<PartCoverage>,
+}
+
+impl ChipCoverage {
+    /// Number of wire bits that toggled both ways, and the number of wire bits
+    pub fn toggled_bits(&self) -> (usize, usize) {
+        self.wires.iter().fold((0, 0), |(toggled, total), wire| {
+            (toggled + wire.toggled_bits(), total + wire.rose.len())
+        })
+    }
+
+    /// Number of parts exercised
+    pub fn exercised_parts(&self) -> usize {
+        self.parts.iter().filter(|part| part.exercised).count()
+    }
+}
+
+/// Coverage of a chip hierarchy during a test, one entry per chip definition
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Coverage {
+    /// Holds the chips in the order they first appear in the hierarchy, the tested chip first
+    pub chips: Vec<ChipCoverage>,
+}
+
+impl Coverage {
+    /// Coverage of the chip being simulated, from the toggles recorded since
+    /// [Simulator::record_toggles](crate::Simulator::record_toggles)
+    ///
+    /// `chip` must be the chip the simulator was built from.  Spans are given for chips whose source the project
+    /// knows, as long as the source still parses to the simulated chip.
+    pub fn from_simulator(
+        simulator: &Simulator,
+        chip: &Chip,
+        project: &Project,
+    ) -> Result<Coverage, SimulationError> {
+        let toggles = simulator.toggles().ok_or_else(|| {
+            SimulationError::InvalidScript("toggles were not recorded".to_string())
+        })?;
+        let netlist = simulator.netlist();
+        let mut chips: Vec<ChipCoverage> = vec![];
+        for (depth, instance) in netlist.instances.iter().enumerate() {
+            let definition = match project.resolve(&instance.chip) {
+                _ if depth == 0 => chip,
+                Some(Resolved::Chip(definition)) => definition,
+                _ => continue,
+            };
+            let index = match chips
+                .iter()
+                .position(|covered| covered.chip == definition.name)
+            {
+                Some(index) => index,
+                None => {
+                    chips.push(empty(definition, spans(definition, project).as_ref()));
+                    chips.len() - 1
+                }
+            };
+            cover(
+                &mut chips[index],
+                definition,
+                &instance.path,
+                netlist,
+                toggles,
+                project,
+            );
+        }
+        Ok(Coverage { chips })
+    }
+
+    /// Coverage of one chip, by name
+    pub fn chip(&self, name: &str) -> Option<&ChipCoverage> {
+        self.chips.iter().find(|chip| chip.chip == name)
+    }
+
+    /// The coverage as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for Coverage {
+    /// A summary line per chip, followed by the bits that never rose or fell and the parts never exercised
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let at = |line: Option<usize>| {
+            line.map(|line| format!(" (line {})", line))
+                .unwrap_or_default()
+        };
+        for chip in &self.chips {
+            let (toggled, bits) = chip.toggled_bits();
+            writeln!(
+                f,
+                "{} ({} instance{}): {}/{} wire bits toggled, {}/{} parts exercised",
+                chip.chip,
+                chip.instances,
+                if chip.instances == 1 { "" } else { "s" },
+                toggled,
+                bits,
+                chip.exercised_parts(),
+                chip.parts.len()
+            )?;
+            for wire in chip.wires.iter().filter(|wire| !wire.toggled()) {
+                let bits = |missing: &dyn Fn(usize) -> bool| {
+                    (0..wire.rose.len())
+                        .filter(|&bit| missing(bit))
+                        .map(|bit| wire.bit(bit))
+                        .collect::<Vec<_>>()
+                };
+                let never = bits(&|bit| !wire.rose[bit] && !wire.fell[bit]);
+                let rose = bits(&|bit| !wire.rose[bit] && wire.fell[bit]);
+                let fell = bits(&|bit| wire.rose[bit] && !wire.fell[bit]);
+                for (names, what) in [
+                    (never, "never toggled"),
+                    (rose, "never rose"),
+                    (fell, "never fell"),
+                ] {
+                    if !names.is_empty() {
+                        writeln!(f, "  {} {}{}", names.join(", "), what, at(wire.line))?;
+                    }
+                }
+            }
+            for part in chip.parts.iter().filter(|part| !part.exercised) {
+                writeln!(f, "  part {} never exercised{}", part.name, at(part.line))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run a test script against a chip like [run_script_with_limits](crate::runner::run_script_with_limits),
+/// recording the coverage it achieves
+pub fn cover_script(
+    script: &TestScript,
+    chip: &Chip,
+    project: &Project,
+    directory: Option<&Path>,
+    limits: &Limits,
+) -> Result<(TestRun, Coverage), SimulationError> {
+    let started = Instant::now();
+    let mut simulator = Simulator::from_netlist(flatten_with_limits(chip, project, limits)?)?;
+    simulator.record_toggles();
+    let (run, simulator) = run_simulator(script, simulator, directory, limits, started)?;
+    let coverage = Coverage::from_simulator(&simulator, chip, project)?;
+    Ok((run, coverage))
+}
+
+/// The source of a chip and where its pieces are written, if the project knows it and it is still current
+fn spans<'a>(chip: &Chip, project: &'a Project) -> Option<(&'a str, ChipSpans)> {
+    let source = project.source(&chip.name)?;
+    match parse_hdl_spans(source) {
+        Ok((parsed, spans)) if parsed == *chip => Some((source, spans)),
+        _ => None,
+    }
+}
+
+/// Coverage of a chip before any of its instances are counted
+fn empty(chip: &Chip, source: Option<&(&str, ChipSpans)>) -> ChipCoverage {
+    let locate = |span: Option<Span>| match (span, source) {
+        (Some(span), Some((text, _))) => (Some(span), Some(span.line_column(text).0)),
+        _ => (None, None),
+    };
+    let spans = source.map(|(_, spans)| spans);
+    let mut wires: Vec<WireCoverage> = vec![];
+    let mut wire = |name: &str, span: Option<Span>| {
+        if name == "true" || name == "false" || wires.iter().any(|wire| wire.name == name) {
+            return;
+        }
+        let (span, line) = locate(span);
+        wires.push(WireCoverage {
+            name: name.to_string(),
+            rose: vec![],
+            fell: vec![],
+            span,
+            line,
+        });
+    };
+    for (index, pin) in chip.inputs.iter().enumerate() {
+        wire(
+            &pin.name,
+            spans.and_then(|spans| spans.inputs.get(index).copied()),
+        );
+    }
+    for (index, pin) in chip.outputs.iter().enumerate() {
+        wire(
+            &pin.name,
+            spans.and_then(|spans| spans.outputs.get(index).copied()),
+        );
+    }
+    for (index, part) in chip.parts.iter().enumerate() {
+        for (connection, pin) in part.external.iter().enumerate() {
+            let span = spans
+                .and_then(|spans| spans.parts.get(index))
+                .and_then(|part| part.external.get(connection).copied());
+            wire(&pin.name, span);
+        }
+    }
+    let parts = chip
+        .parts
+        .iter()
+        .enumerate()
+        .map(|(index, part)| {
+            let (span, line) = locate(
+                spans
+                    .and_then(|spans| spans.parts.get(index))
+                    .map(|part| part.span),
+            );
+            PartCoverage {
+                name: part.name.clone(),
+                index,
+                exercised: false,
+                span,
+                line,
+            }
+        })
+        .collect();
+    ChipCoverage {
+        chip: chip.name.clone(),
+        instances: 0,
+        wires,
+        parts,
+    }
+}
+
+/// Add the toggles of one instance of a chip to its coverage
+fn cover(
+    coverage: &mut ChipCoverage,
+    chip: &Chip,
+    path: &str,
+    netlist: &Netlist,
+    toggles: &Toggles,
+    project: &Project,
+) {
+    let nets = |name: &str| netlist.wires.get(&format!("{}/{}", path, name));
+    coverage.instances += 1;
+    for wire in coverage.wires.iter_mut() {
+        let nets = match nets(&wire.name) {
+            Some(nets) => nets,
+            None => continue,
+        };
+        wire.rose.resize(nets.len(), false);
+        wire.fell.resize(nets.len(), false);
+        for (bit, &net) in nets.iter().enumerate() {
+            wire.rose[bit] |= toggles.rose(net);
+            wire.fell[bit] |= toggles.fell(net);
+        }
+    }
+    for (part, covered) in chip.parts.iter().zip(coverage.parts.iter_mut()) {
+        let inputs: Vec<&str> = match project.resolve(&part.name) {
+            Some(Resolved::Chip(definition)) => definition
+                .inputs
+                .iter()
+                .map(|pin| pin.name.as_str())
+                .collect(),
+            Some(Resolved::Primitive(primitive)) => {
+                primitive.inputs().iter().map(|(name, _)| *name).collect()
+            }
+            None => vec![],
+        };
+        let changed = part
+            .internal
+            .iter()
+            .zip(&part.external)
+            .filter(|(internal, _)| inputs.contains(&internal.name.as_str()))
+            .filter_map(|(_, external)| {
+                let nets = nets(&external.name)?;
+                match external.range() {
+                    Some((start, end)) => nets.get(start..=end),
+                    None => Some(&nets[..]),
+                }
+            })
+            .flatten()
+            .any(|&net| toggles.rose(net) || toggles.fell(net));
+        covered.exercised |= changed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::coverage::cover_script;
+    use crate::limits::Limits;
+    use crate::test_script::parse_tst;
+    use crate::Project;
+
+    #[test]
+    fn reports_untoggled_wires() {
+        let mut project = Project::new();
+        let hdl = "CHIP Pick {
+    IN a, b, sel;
+    OUT out;
+    PARTS:
+    Mux(a=a, b=b, sel=sel, out=out);
+}";
+        let _ = project.add_hdl(hdl).unwrap();
+        let pick = project.chip("Pick").unwrap();
+        // sel is never set, so only a reaches the output
+        let script = parse_tst("set a 1, eval; set a 0, eval; set b 1, eval;").unwrap();
+        let (_, coverage) =
+            cover_script(&script, pick, &project, None, &Limits::default()).unwrap();
+
+        let chip = coverage.chip("Pick").unwrap();
+        assert_eq!(chip.toggled_bits(), (2, 4));
+        let sel = &chip.wires[2];
+        assert_eq!((sel.name.as_str(), sel.toggled()), ("sel", false));
+        let span = sel.span.unwrap();
+        assert_eq!(&hdl[span.start..span.end], "sel");
+        assert_eq!(sel.line, Some(2));
+        let b = &chip.wires[1];
+        assert_eq!((b.rose[0], b.fell[0]), (true, false));
+        assert_eq!(chip.exercised_parts(), 1);
+
+        let mux = coverage.chip("Mux").unwrap();
+        assert_eq!(mux.instances, 1);
+        assert!(!mux
+            .wires
+            .iter()
+            .find(|wire| wire.name == "sel")
+            .unwrap()
+            .toggled());
+        assert!(mux.wires[0].span.is_some());
+
+        let text = coverage.to_string();
+        assert!(text.starts_with("Pick (1 instance): 2/4 wire bits toggled, 1/1 parts exercised\n"));
+        assert!(text.contains("  sel never toggled (line 2)\n"));
+        assert!(text.contains("  b never fell (line 2)\n"));
+    }
+}
@@ -0,0 +1,369 @@
+//! Random differential testing of a chip against a reference implementation
+//!
+//! Both chips are driven through the same seeded random sequence of input changes, evaluations and, for
+//! sequential chips, clock cycles, and their outputs are compared after every step.  The first divergence is
+//! shrunk to a short sequence that still reproduces it and can be printed as test script commands.
+
+use crate::equivalence::{check_interface, Values};
+use crate::flatten::{flatten, Netlist};
+use crate::random::Rng;
+use crate::simulator::{SimulationError, Simulator};
+use crate::{Chip, Project};
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+/// What happens after the inputs of a step are set
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Action {
+    /// Evaluate the combinational logic
+    Eval,
+    /// Run a full clock cycle, a tick followed by a tock
+    Cycle,
+}
+
+/// Input changes followed by an evaluation or a clock cycle
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Step {
+    /// Holds the inputs set by the step, in order; other inputs keep their values
+    pub inputs: Values,
+    /// Holds what happens once the inputs are set
+    pub action: Action,
+}
+
+/// A sequence of steps after which two chips have different outputs
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Divergence {
+    /// Holds the name of the chip under test
+    pub chip: String,
+    /// Holds the name of the reference chip
+    pub reference: String,
+    /// Holds the steps, from a freshly created chip, that lead to the difference
+    pub steps: Vec<Step>,
+    /// Holds the outputs of the chip under test after the last step
+    pub outputs: Values,
+    /// Holds the outputs of the reference chip after the last step
+    pub expected: Values,
+}
+
+impl fmt::Display for Divergence {
+    /// The steps as test script commands, with the differing outputs as comments
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "// {} differs from {} after {} steps",
+            self.chip,
+            self.reference,
+            self.steps.len()
+        )?;
+        for step in &self.steps {
+            let mut commands: Vec<String> = step
+                .inputs
+                .iter()
+                .map(|(name, value)| format!("set {} {}", name, value))
+                .collect();
+            commands.push(match step.action {
+                Action::Eval => "eval".to_string(),
+                Action::Cycle => "tick, tock".to_string(),
+            });
+            commands.push("output".to_string());
+            writeln!(f, "{};", commands.join(", "))?;
+        }
+        for ((name, found), (_, expected)) in self.outputs.iter().zip(&self.expected) {
+            if found != expected {
+                writeln!(f, "// {}: expected {}, found {}", name, expected, found)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Compare a chip, whose parts are resolved against `project`, against a reference whose parts are resolved
+/// against `reference_project`
+///
+/// A builtin reference should be resolved against [Project::new], so that a bug in one of the project's own parts
+/// does not show up on both sides.
+pub fn differential_chips(
+    chip: &Chip,
+    project: &Project,
+    reference: &Chip,
+    reference_project: &Project,
+    steps: usize,
+    seed: u64,
+) -> Result<Option<Divergence>, SimulationError> {
+    differential_test(
+        &flatten(chip, project)?,
+        &flatten(reference, reference_project)?,
+        steps,
+        seed,
+    )
+}
+
+/// Drive two flattened chips with the same interface through `steps` random steps generated from `seed`
+///
+/// Each step sets some of the inputs to random values, then evaluates or, if either chip is clocked, randomly
+/// evaluates or runs a clock cycle.  Returns the first divergence, shrunk to a minimal reproducing sequence, or
+/// `None` if the chips agreed throughout.
+pub fn differential_test(
+    chip: &Netlist,
+    reference: &Netlist,
+    steps: usize,
+    seed: u64,
+) -> Result<Option<Divergence>, SimulationError> {
+    check_interface(chip, reference)?;
+    let pair = Pair {
+        chip: Simulator::from_netlist(chip.clone())?,
+        reference: Simulator::from_netlist(reference.clone())?,
+    };
+    let clocked = [chip, reference]
+        .iter()
+        .any(|netlist| netlist.cells.iter().any(|cell| cell.primitive.is_clocked()));
+
+    let mut rng = Rng::new(seed);
+    let mut sequence = Vec::with_capacity(steps);
+    for index in 0..steps {
+        let mut inputs = vec![];
+        for bus in &chip.inputs {
+            // every input starts random, then changes about half the time
+            if index == 0 || rng.bits(1) == 1 {
+                inputs.push((bus.name.clone(), rng.bits(bus.nets.len())));
+            }
+        }
+        let action = if clocked && rng.bits(1) == 1 {
+            Action::Cycle
+        } else {
+            Action::Eval
+        };
+        sequence.push(Step { inputs, action });
+    }
+
+    match pair.run(&sequence)? {
+        None => Ok(None),
+        Some(length) => {
+            let steps = pair.shrink(sequence[..length].to_vec())?;
+            let (outputs, expected) = pair.outputs(&steps)?;
+            Ok(Some(Divergence {
+                chip: chip.name.clone(),
+                reference: reference.name.clone(),
+                steps,
+                outputs,
+                expected,
+            }))
+        }
+    }
+}
+
+/// Most sequences replayed while shrinking a divergence
+const MAX_REPLAYS: usize = 5000;
+
+/// Freshly created simulators of both chips
+struct Pair {
+    chip: Simulator,
+    reference: Simulator,
+}
+
+impl Pair {
+    /// Replay steps on both chips and return their outputs after each
+    fn replay(&self, steps: &[Step]) -> Result<Vec<(Values, Values)>, SimulationError> {
+        let mut chip = self.chip.clone();
+        let mut reference = self.reference.clone();
+        let mut outputs = vec![];
+        for step in steps {
+            for simulator in [&mut chip, &mut reference].iter_mut() {
+                for (name, value) in &step.inputs {
+                    simulator.set(name, *value)?;
+                }
+                match step.action {
+                    Action::Eval => simulator.eval(),
+                    Action::Cycle => simulator.step(),
+                }
+            }
+            outputs.push((values(&chip, &chip)?, values(&chip, &reference)?));
+        }
+        Ok(outputs)
+    }
+
+    /// Number of steps up to and including the first after which the outputs differ
+    fn run(&self, steps: &[Step]) -> Result<Option<usize>, SimulationError> {
+        Ok(self
+            .replay(steps)?
+            .iter()
+            .position(|(chip, reference)| chip != reference)
+            .map(|index| index + 1))
+    }
+
+    fn outputs(&self, steps: &[Step]) -> Result<(Values, Values), SimulationError> {
+        Ok(self.replay(steps)?.pop().unwrap_or_default())
+    }
+
+    /// Remove steps and input changes, and simplify values, for as long as the chips still diverge
+    ///
+    /// Chunks of steps are removed first, halving their size down to single steps, then the input changes and
+    /// actions of the remaining steps are simplified one at a time.  Candidates are built only when tried, and
+    /// after [MAX_REPLAYS] replays the shortest sequence found so far is kept.
+    fn shrink(&self, mut steps: Vec<Step>) -> Result<Vec<Step>, SimulationError> {
+        let mut replays = 0;
+        let mut shrunk = true;
+        while shrunk && replays < MAX_REPLAYS {
+            shrunk = false;
+            let mut chunk = steps.len().div_ceil(2);
+            while chunk > 0 && replays < MAX_REPLAYS {
+                let mut start = 0;
+                while start < steps.len() && replays < MAX_REPLAYS {
+                    let end = (start + chunk).min(steps.len());
+                    let without = steps[..start]
+                        .iter()
+                        .chain(&steps[end..])
+                        .cloned()
+                        .collect();
+                    match self.attempt(without, &mut replays)? {
+                        Some(smaller) => {
+                            steps = smaller;
+                            shrunk = true;
+                        }
+                        None => start = end,
+                    }
+                }
+                chunk /= 2;
+            }
+
+            let mut index = 0;
+            while index < steps.len() && replays < MAX_REPLAYS {
+                let mut input = 0;
+                while index < steps.len()
+                    && input < steps[index].inputs.len()
+                    && replays < MAX_REPLAYS
+                {
+                    let mut without = steps.clone();
+                    let _ = without[index].inputs.remove(input);
+                    if let Some(smaller) = self.attempt(without, &mut replays)? {
+                        steps = smaller;
+                        shrunk = true;
+                        continue;
+                    }
+                    if steps[index].inputs[input].1 != 0 {
+                        let mut zeroed = steps.clone();
+                        zeroed[index].inputs[input].1 = 0;
+                        if let Some(smaller) = self.attempt(zeroed, &mut replays)? {
+                            steps = smaller;
+                            shrunk = true;
+                        }
+                    }
+                    input += 1;
+                }
+                if index < steps.len()
+                    && steps[index].action == Action::Cycle
+                    && replays < MAX_REPLAYS
+                {
+                    let mut evaluated = steps.clone();
+                    evaluated[index].action = Action::Eval;
+                    if let Some(smaller) = self.attempt(evaluated, &mut replays)? {
+                        steps = smaller;
+                        shrunk = true;
+                    }
+                }
+                index += 1;
+            }
+        }
+        Ok(steps)
+    }
+
+    /// Replay a candidate sequence, returning it up to the divergence if the chips still diverge
+    fn attempt(
+        &self,
+        mut candidate: Vec<Step>,
+        replays: &mut usize,
+    ) -> Result<Option<Vec<Step>>, SimulationError> {
+        *replays += 1;
+        Ok(self.run(&candidate)?.map(|length| {
+            candidate.truncate(length);
+            candidate
+        }))
+    }
+}
+
+/// Outputs of a simulator, in the order of the chip under test's outputs
+fn values(chip: &Simulator, simulator: &Simulator) -> Result<Values, SimulationError> {
+    chip.netlist()
+        .outputs
+        .iter()
+        .map(|bus| Ok((bus.name.clone(), simulator.get(&bus.name)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::differential::{differential_chips, Action};
+    use crate::Project;
+
+    #[test]
+    fn agrees_with_itself() {
+        let project = Project::new();
+        let alu = project.chip("ALU").unwrap();
+        assert_eq!(
+            differential_chips(alu, &project, alu, &project, 200, 1).unwrap(),
+            None
+        );
+        let pc = project.chip("PC").unwrap();
+        assert_eq!(
+            differential_chips(pc, &project, pc, &project, 200, 1).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn shrinks_divergence() {
+        let mut project = Project::new();
+        // forgets to reset
+        let broken = project
+            .add_hdl(
+                "CHIP PC {
+                    IN in[16], load, inc, reset;
+                    OUT out[16];
+                    PARTS:
+                    Inc16(in=feedback, out=next);
+                    Mux16(a=feedback, b=next, sel=inc, out=incremented);
+                    Mux16(a=incremented, b=in, sel=load, out=loaded);
+                    Register(in=loaded, load=true, out=out, out=feedback);
+                }",
+            )
+            .unwrap()
+            .clone();
+        let builtins = Project::new();
+        let reference = builtins.chip("PC").unwrap();
+        let divergence = differential_chips(&broken, &project, reference, &builtins, 500, 7)
+            .unwrap()
+            .unwrap();
+        // the shortest reproduction asks for an increment, then resets
+        assert_eq!(divergence.steps.len(), 2);
+        assert_eq!(divergence.steps[1].action, Action::Cycle);
+        assert_eq!(divergence.expected, vec![("out".to_string(), 0)]);
+        assert_eq!(
+            divergence.to_string(),
+            "// PC differs from PC after 2 steps\n\
+             set inc 1, eval, output;\n\
+             set reset 1, tick, tock, output;\n\
+             // out: expected 0, found 1\n"
+        );
+    }
+
+    #[test]
+    fn resolves_references_separately() {
+        let mut project = Project::new();
+        // a Not that always outputs 1, used by the builtin And
+        let _ = project
+            .add_hdl("CHIP Not { IN in; OUT out; PARTS: Nand(a=in, b=false, out=out); }")
+            .unwrap();
+        let builtins = Project::new();
+        let and = project.chip("And").unwrap();
+        let reference = builtins.chip("And").unwrap();
+        assert_eq!(
+            differential_chips(and, &project, reference, &project, 50, 1).unwrap(),
+            None
+        );
+        assert!(
+            differential_chips(and, &project, reference, &builtins, 50, 1)
+                .unwrap()
+                .is_some()
+        );
+    }
+}
@@ -0,0 +1,411 @@
+//! Equivalence checking between two implementations of the same chip interface
+//!
+//! Chips whose inputs are at most [MAX_INPUT_BITS] wide are compared on every input combination.  Wider chips are
+//! compared symbolically with reduced ordered binary decision diagrams, whose variables interleave the input
+//! buses bit by bit so adders and multiplexors stay small.  If the diagrams grow past [MAX_BDD_NODES] the check
+//! falls back to random simulation and may report [Equivalence::Unknown].
+
+use crate::bitsliced::Program;
+use crate::flatten::{flatten, Bus, Netlist, Primitive, FALSE, TRUE};
+use crate::random::Rng;
+use crate::simulator::SimulationError;
+use crate::truth_table::MAX_INPUT_BITS;
+use crate::{Chip, Project};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Largest number of decision diagram nodes built before giving up on a symbolic proof
+pub const MAX_BDD_NODES: usize = 1 << 21;
+
+/// Number of random input vectors tried when a symbolic proof is abandoned
+pub const RANDOM_VECTORS: usize = 1 << 16;
+
+/// Values of named buses
+pub type Values = Vec<(String, u64)>;
+
+/// An input on which two chips disagree
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Counterexample {
+    /// Holds the value of every input bus
+    pub inputs: Values,
+    /// Holds the outputs of the first chip
+    pub left: Values,
+    /// Holds the outputs of the second chip
+    pub right: Values,
+}
+
+/// Outcome of comparing two chips
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Equivalence {
+    /// The chips produce the same outputs for every input
+    Equivalent,
+    /// The chips differ on the given input
+    Different(Counterexample),
+    /// The chips were too large to prove equivalent and random simulation found no difference
+    Unknown,
+}
+
+/// Compare two chips whose parts are resolved against the same project
+pub fn check_chips(
+    left: &Chip,
+    right: &Chip,
+    project: &Project,
+) -> Result<Equivalence, SimulationError> {
+    check_equivalence(&flatten(left, project)?, &flatten(right, project)?)
+}
+
+/// Compare two flattened combinational chips with the same input and output buses
+pub fn check_equivalence(left: &Netlist, right: &Netlist) -> Result<Equivalence, SimulationError> {
+    check_interface(left, right)?;
+    let miter = Miter {
+        left: Program::compile(left)?,
+        right: Program::compile(right)?,
+        left_netlist: left,
+        right_netlist: right,
+    };
+
+    let bits: usize = left.inputs.iter().map(|bus| bus.nets.len()).sum();
+    if bits <= MAX_INPUT_BITS {
+        let vectors: Vec<Vec<u64>> = (0..1u64 << bits)
+            .map(|combination| {
+                let mut shift = bits;
+                left.inputs
+                    .iter()
+                    .map(|bus| {
+                        shift -= bus.nets.len();
+                        combination >> shift & ((1 << bus.nets.len()) - 1)
+                    })
+                    .collect()
+            })
+            .collect();
+        return miter.search(&vectors);
+    }
+
+    if let Some(result) = symbolic(left, right) {
+        return match result {
+            None => Ok(Equivalence::Equivalent),
+            Some(vector) => Ok(miter.search(&[vector])?),
+        };
+    }
+
+    let mut rng = Rng::new(0);
+    let vectors: Vec<Vec<u64>> = (0..RANDOM_VECTORS)
+        .map(|_| {
+            left.inputs
+                .iter()
+                .map(|bus| rng.bits(bus.nets.len()))
+                .collect()
+        })
+        .collect();
+    match miter.search(&vectors)? {
+        Equivalence::Equivalent => Ok(Equivalence::Unknown),
+        different => Ok(different),
+    }
+}
+
+/// Fail unless two chips have input and output buses of the same names and widths
+pub(crate) fn check_interface(left: &Netlist, right: &Netlist) -> Result<(), SimulationError> {
+    fn interface(buses: &[Bus]) -> Vec<(&str, usize)> {
+        let mut pins: Vec<(&str, usize)> = buses
+            .iter()
+            .map(|bus| (bus.name.as_str(), bus.nets.len()))
+            .collect();
+        pins.sort_unstable();
+        pins
+    }
+    if interface(&left.inputs) != interface(&right.inputs)
+        || interface(&left.outputs) != interface(&right.outputs)
+    {
+        return Err(SimulationError::InterfaceMismatch(format!(
+            "{} and {} have different pins",
+            left.name, right.name
+        )));
+    }
+    Ok(())
+}
+
+/// Both chips compiled for simulation, with input vectors ordered by the left chip's inputs
+struct Miter<'a> {
+    left: Program,
+    right: Program,
+    left_netlist: &'a Netlist,
+    right_netlist: &'a Netlist,
+}
+
+impl Miter<'_> {
+    /// Return the first vector on which the chips disagree
+    fn search(&self, vectors: &[Vec<u64>]) -> Result<Equivalence, SimulationError> {
+        let names = |buses: &[Bus]| buses.iter().map(|bus| bus.name.clone()).collect::<Vec<_>>();
+        let inputs = names(&self.left_netlist.inputs);
+        let outputs = names(&self.left_netlist.outputs);
+        let reorder = |values: &[u64], from: &[Bus], to: &[String]| -> Vec<u64> {
+            to.iter()
+                .map(|name| values[from.iter().position(|bus| &bus.name == name).unwrap_or(0)])
+                .collect()
+        };
+
+        let right_order = names(&self.right_netlist.inputs);
+        let right_vectors: Vec<Vec<u64>> = vectors
+            .iter()
+            .map(|vector| reorder(vector, &self.left_netlist.inputs, &right_order))
+            .collect();
+        let left_results = self.left.run(vectors)?;
+        let right_results = self.right.run(&right_vectors)?;
+        for ((vector, left), right) in vectors.iter().zip(left_results).zip(right_results) {
+            let right = reorder(&right, &self.right_netlist.outputs, &outputs);
+            if left != right {
+                let label = |values: Vec<u64>, names: &[String]| {
+                    names.iter().cloned().zip(values).collect::<Values>()
+                };
+                return Ok(Equivalence::Different(Counterexample {
+                    inputs: label(vector.clone(), &inputs),
+                    left: label(left, &outputs),
+                    right: label(right, &outputs),
+                }));
+            }
+        }
+        Ok(Equivalence::Equivalent)
+    }
+}
+
+const ZERO: u32 = 0;
+const ONE: u32 = 1;
+
+/// A shared, reduced ordered binary decision diagram
+struct Bdd {
+    /// variable, low child and high child of every node; the terminals come first
+    nodes: Vec<(u32, u32, u32)>,
+    unique: HashMap<(u32, u32, u32), u32>,
+    and_cache: HashMap<(u32, u32), u32>,
+    xor_cache: HashMap<(u32, u32), u32>,
+}
+
+impl Bdd {
+    fn new() -> Bdd {
+        Bdd {
+            nodes: vec![(u32::MAX, ZERO, ZERO), (u32::MAX, ONE, ONE)],
+            unique: HashMap::new(),
+            and_cache: HashMap::new(),
+            xor_cache: HashMap::new(),
+        }
+    }
+
+    fn node(&mut self, var: u32, low: u32, high: u32) -> u32 {
+        if low == high {
+            return low;
+        }
+        let next = self.nodes.len() as u32;
+        let nodes = &mut self.nodes;
+        *self.unique.entry((var, low, high)).or_insert_with(|| {
+            nodes.push((var, low, high));
+            next
+        })
+    }
+
+    /// Top variable of two nodes and the cofactors of each with respect to it
+    fn split(&self, a: u32, b: u32) -> (u32, (u32, u32), (u32, u32)) {
+        let (va, la, ha) = self.nodes[a as usize];
+        let (vb, lb, hb) = self.nodes[b as usize];
+        let var = va.min(vb);
+        let cofactors = |v, l, h, n| if v == var { (l, h) } else { (n, n) };
+        (var, cofactors(va, la, ha, a), cofactors(vb, lb, hb, b))
+    }
+
+    fn and(&mut self, a: u32, b: u32) -> u32 {
+        if a == ZERO || b == ZERO {
+            return ZERO;
+        }
+        if a == ONE || a == b {
+            return b;
+        }
+        if b == ONE {
+            return a;
+        }
+        let key = (a.min(b), a.max(b));
+        if let Some(&result) = self.and_cache.get(&key) {
+            return result;
+        }
+        let (var, (la, ha), (lb, hb)) = self.split(a, b);
+        let low = self.and(la, lb);
+        let high = self.and(ha, hb);
+        let result = self.node(var, low, high);
+        let _ = self.and_cache.insert(key, result);
+        result
+    }
+
+    fn xor(&mut self, a: u32, b: u32) -> u32 {
+        if a == b {
+            return ZERO;
+        }
+        if a == ZERO {
+            return b;
+        }
+        if b == ZERO {
+            return a;
+        }
+        if a == ONE && b == ONE {
+            return ZERO;
+        }
+        let key = (a.min(b), a.max(b));
+        if let Some(&result) = self.xor_cache.get(&key) {
+            return result;
+        }
+        let (var, (la, ha), (lb, hb)) = self.split(a, b);
+        let low = self.xor(la, lb);
+        let high = self.xor(ha, hb);
+        let result = self.node(var, low, high);
+        let _ = self.xor_cache.insert(key, result);
+        result
+    }
+
+    /// Build the diagram of every net, or `None` if it grows too large
+    fn netlist(&mut self, netlist: &Netlist, vars: &HashMap<&str, Vec<u32>>) -> Option<Vec<u32>> {
+        let mut nodes = vec![ZERO; netlist.nets];
+        nodes[FALSE] = ZERO;
+        nodes[TRUE] = ONE;
+        for bus in &netlist.inputs {
+            for (&net, &var) in bus.nets.iter().zip(&vars[bus.name.as_str()]) {
+                nodes[net] = self.node(var, ZERO, ONE);
+            }
+        }
+        for index in netlist.topological_order().ok()? {
+            let cell = &netlist.cells[index];
+            if cell.primitive != Primitive::Nand {
+                return None;
+            }
+            let and = self.and(nodes[cell.inputs[0][0]], nodes[cell.inputs[1][0]]);
+            nodes[cell.outputs[0][0]] = self.xor(and, ONE);
+            if self.nodes.len() > MAX_BDD_NODES {
+                return None;
+            }
+        }
+        Some(nodes)
+    }
+}
+
+/// Prove two chips equivalent symbolically
+///
+/// Returns `None` if the proof was abandoned, `Some(None)` if the chips are equivalent and `Some(Some(vector))`
+/// with a distinguishing input vector, ordered by the left chip's inputs, if they are not.
+fn symbolic(left: &Netlist, right: &Netlist) -> Option<Option<Vec<u64>>> {
+    // interleave the buses so that bit i of every input is ordered before bit i + 1 of any input
+    let mut vars: HashMap<&str, Vec<u32>> = HashMap::new();
+    let mut next = 0;
+    let widest = left
+        .inputs
+        .iter()
+        .map(|bus| bus.nets.len())
+        .max()
+        .unwrap_or(0);
+    for bit in 0..widest {
+        for bus in left.inputs.iter().filter(|bus| bus.nets.len() > bit) {
+            vars.entry(bus.name.as_str()).or_default().push(next);
+            next += 1;
+        }
+    }
+
+    let mut bdd = Bdd::new();
+    let left_nodes = bdd.netlist(left, &vars)?;
+    let right_nodes = bdd.netlist(right, &vars)?;
+    for bus in &left.outputs {
+        let other = &right
+            .outputs
+            .iter()
+            .find(|other| other.name == bus.name)?
+            .nets;
+        for (&l, &r) in bus.nets.iter().zip(other) {
+            let (l, r) = (left_nodes[l], right_nodes[r]);
+            if l == r {
+                continue;
+            }
+            // walk any path to the true terminal of the difference
+            let mut assignment = HashMap::new();
+            let mut node = bdd.xor(l, r);
+            while node > ONE {
+                let (var, low, high) = bdd.nodes[node as usize];
+                let take_high = low == ZERO;
+                let _ = assignment.insert(var, take_high);
+                node = if take_high { high } else { low };
+            }
+            let vector = left
+                .inputs
+                .iter()
+                .map(|bus| {
+                    vars[bus.name.as_str()]
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, var)| assignment.get(var) == Some(&true))
+                        .fold(0, |value, (bit, _)| value | 1 << bit)
+                })
+                .collect();
+            return Some(Some(vector));
+        }
+    }
+    Some(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::equivalence::{check_chips, check_equivalence, Equivalence};
+    use crate::simulator::SimulationError;
+    use crate::{flatten, parse_hdl, Project};
+
+    const MUX4WAY16: &str = "CHIP Mux4Way16 {
+        IN a[16], b[16], c[16], d[16], sel[2];
+        OUT out[16];
+        PARTS:
+        Mux16(a=a, b=c, sel=sel[1], out=ac);
+        Mux16(a=b, b=d, sel=sel[1], out=bd);
+        Mux16(a=ac, b=bd, sel=sel[0], out=out);
+    }";
+
+    #[test]
+    fn proves_wide_chips_equivalent() {
+        let mut student = Project::new();
+        let _ = student.add_hdl(MUX4WAY16).unwrap();
+        let reference = Project::new();
+        let chip = student.chip("Mux4Way16").unwrap();
+        let result = check_equivalence(
+            &flatten(chip, &student).unwrap(),
+            &flatten(reference.chip("Mux4Way16").unwrap(), &reference).unwrap(),
+        );
+        assert_eq!(result.unwrap(), Equivalence::Equivalent);
+    }
+
+    #[test]
+    fn finds_counterexamples() {
+        let project = Project::new();
+        let broken =
+            parse_hdl(&MUX4WAY16.replace("sel=sel[0], out=out", "sel=sel[1], out=out")).unwrap();
+        match check_chips(&broken, project.chip("Mux4Way16").unwrap(), &project).unwrap() {
+            Equivalence::Different(counterexample) => {
+                assert_ne!(counterexample.left, counterexample.right);
+                assert_eq!(counterexample.inputs.len(), 5);
+            }
+            other => panic!("expected a counterexample, got {:?}", other),
+        }
+
+        let xor =
+            parse_hdl("CHIP Xor { IN a, b; OUT out; PARTS: Or(a=a, b=b, out=out); }").unwrap();
+        match check_chips(&xor, project.chip("Xor").unwrap(), &project).unwrap() {
+            Equivalence::Different(counterexample) => assert_eq!(
+                counterexample.inputs,
+                vec![("a".to_string(), 1), ("b".to_string(), 1)]
+            ),
+            other => panic!("expected a counterexample, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn requires_matching_interfaces() {
+        let project = Project::new();
+        assert!(matches!(
+            check_chips(
+                project.chip("And").unwrap(),
+                project.chip("Not").unwrap(),
+                &project
+            ),
+            Err(SimulationError::InterfaceMismatch(_))
+        ));
+    }
+}
@@ -0,0 +1,246 @@
+//! Stuck-at fault injection and fault coverage of tests
+//!
+//! A stuck-at fault holds one net of a flattened chip at 0 or 1 whatever drives it.  A test detects a fault when
+//! what it observes of the faulty chip differs from what it observes of the fault-free one, so the share of all
+//! single stuck-at faults a test detects measures how thoroughly it exercises the chip.
+
+use crate::flatten::{Net, Netlist, FALSE, TRUE};
+use crate::simulator::{SimulationError, Simulator};
+use core::fmt;
+use dict_derive::{FromPyObject, IntoPyObject};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// A net held at a constant value
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, FromPyObject, IntoPyObject)]
+pub struct Fault {
+    /// Holds the shallowest hierarchical name of the net
+    pub wire: String,
+    /// Holds the net within the flattened chip
+    pub net: Net,
+    /// Holds the value the net is stuck at
+    pub value: bool,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} stuck-at-{}", self.wire, self.value as u8)
+    }
+}
+
+/// Every single stuck-at fault of a flattened chip: each net other than the constants stuck at 0 and at 1
+///
+/// Faults are named by the wire closest to the top of the hierarchy that carries the net, which for buses includes
+/// the bit, like `Add16/FullAdder[3]/sum` or `out[3]`.
+pub fn faults(netlist: &Netlist) -> Vec<Fault> {
+    let mut names: BTreeMap<Net, String> = BTreeMap::new();
+    for (wire, nets) in &netlist.wires {
+        let wire = wire
+            .strip_prefix(&format!("{}/", netlist.name))
+            .unwrap_or(wire);
+        for (bit, &net) in nets.iter().enumerate() {
+            if net == FALSE || net == TRUE {
+                continue;
+            }
+            let name = if nets.len() == 1 {
+                wire.to_string()
+            } else {
+                format!("{}[{}]", wire, bit)
+            };
+            let depth = |name: &str| name.matches('/').count();
+            match names.get(&net) {
+                Some(known) if (depth(known), known) <= (depth(&name), &name) => {}
+                _ => {
+                    let _ = names.insert(net, name);
+                }
+            }
+        }
+    }
+    names
+        .into_iter()
+        .flat_map(|(net, wire)| {
+            vec![false, true].into_iter().map(move |value| Fault {
+                wire: wire.clone(),
+                net,
+                value,
+            })
+        })
+        .collect()
+}
+
+/// Which faults of a chip a test detects
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, FromPyObject, IntoPyObject)]
+pub struct FaultReport {
+    /// Holds the name of the chip
+    pub chip: String,
+    /// Holds the faults the test detected
+    pub detected: Vec<Fault>,
+    /// Holds the faults the test missed
+    pub undetected: Vec<Fault>,
+}
+
+impl FaultReport {
+    /// Share of faults detected, between 0 and 1, or 1 if the chip has no faults at all
+    pub fn coverage(&self) -> f64 {
+        let total = self.detected.len() + self.undetected.len();
+        if total == 0 {
+            1.0
+        } else {
+            self.detected.len() as f64 / total as f64
+        }
+    }
+}
+
+impl fmt::Display for FaultReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "{}: {} of {} faults detected ({:.1}%)",
+            self.chip,
+            self.detected.len(),
+            self.detected.len() + self.undetected.len(),
+            self.coverage() * 100.0
+        )?;
+        for fault in &self.undetected {
+            writeln!(f, "  undetected: {}", fault)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run a test against the chip with each single stuck-at fault injected and report which faults it detects
+///
+/// The test drives a fresh simulator and returns what it observed, typically the outputs after each step.  A
+/// fault is detected if the observations differ from those of the fault-free chip, or if the test fails on it.
+pub fn fault_coverage<T, F>(netlist: &Netlist, mut test: F) -> Result<FaultReport, SimulationError>
+where
+    T: PartialEq,
+    F: FnMut(&mut Simulator) -> Result<T, SimulationError>,
+{
+    let simulator = Simulator::from_netlist(netlist.clone())?;
+    let expected = test(&mut simulator.clone())?;
+    let mut report = FaultReport {
+        chip: netlist.name.clone(),
+        detected: vec![],
+        undetected: vec![],
+    };
+    for fault in faults(netlist) {
+        let mut faulty = simulator.clone();
+        faulty.stick_net(fault.net, fault.value);
+        faulty.eval();
+        match test(&mut faulty) {
+            Ok(observed) if observed == expected => report.undetected.push(fault),
+            _ => report.detected.push(fault),
+        }
+    }
+    Ok(report)
+}
+
+/// Fault coverage of applying input vectors one after another and observing every output after each
+///
+/// Each vector holds a value for every input of the chip, in order.  Sequential chips are clocked once after each
+/// vector is applied.
+pub fn vector_coverage(
+    netlist: &Netlist,
+    vectors: &[Vec<u64>],
+) -> Result<FaultReport, SimulationError> {
+    let inputs: Vec<String> = netlist.inputs.iter().map(|bus| bus.name.clone()).collect();
+    let outputs: Vec<String> = netlist.outputs.iter().map(|bus| bus.name.clone()).collect();
+    let clocked = netlist.cells.iter().any(|cell| cell.primitive.is_clocked());
+    fault_coverage(netlist, |simulator| {
+        let mut observed = vec![];
+        for vector in vectors {
+            if vector.len() != inputs.len() {
+                return Err(SimulationError::InvalidVector(format!(
+                    "expected {} values, found {}",
+                    inputs.len(),
+                    vector.len()
+                )));
+            }
+            for (input, &value) in inputs.iter().zip(vector) {
+                simulator.set(input, value)?;
+            }
+            if clocked {
+                simulator.step();
+            } else {
+                simulator.eval();
+            }
+            for output in &outputs {
+                observed.push(simulator.get(output)?);
+            }
+        }
+        Ok(observed)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fault::{fault_coverage, faults, vector_coverage};
+    use crate::flatten::flatten;
+    use crate::{Project, Simulator};
+
+    #[test]
+    fn injects_faults() {
+        let project = Project::new();
+        let mut and = Simulator::new(project.chip("And").unwrap(), &project).unwrap();
+        and.set("a", 1).unwrap();
+        and.set("b", 1).unwrap();
+        and.stick("b", false).unwrap();
+        and.eval();
+        assert_eq!(and.get("out").unwrap(), 0);
+        and.clear_faults();
+        and.stick("nand", false).unwrap();
+        and.eval();
+        assert_eq!(and.get("out").unwrap(), 1);
+        and.clear_faults();
+        and.eval();
+        assert_eq!(and.get("out").unwrap(), 1);
+    }
+
+    #[test]
+    fn measures_coverage() {
+        let project = Project::new();
+        let netlist = flatten(project.chip("And").unwrap(), &project).unwrap();
+        let names: Vec<String> = faults(&netlist).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            names,
+            vec![
+                "a stuck-at-0",
+                "a stuck-at-1",
+                "b stuck-at-0",
+                "b stuck-at-1",
+                "out stuck-at-0",
+                "out stuck-at-1",
+                "nand stuck-at-0",
+                "nand stuck-at-1",
+            ]
+        );
+
+        let exhaustive =
+            vector_coverage(&netlist, &[vec![0, 0], vec![0, 1], vec![1, 0], vec![1, 1]]);
+        assert_eq!(exhaustive.unwrap().coverage(), 1.0);
+        let partial = vector_coverage(&netlist, &[vec![1, 1]]).unwrap();
+        assert_eq!(partial.detected.len(), 4);
+        assert!(partial
+            .to_string()
+            .starts_with("And: 4 of 8 faults detected (50.0%)\n"));
+        assert!(partial.to_string().contains("  undetected: a stuck-at-1\n"));
+
+        let register = flatten(project.chip("Bit").unwrap(), &project).unwrap();
+        let report = fault_coverage(&register, |bit| {
+            bit.set("in", 1)?;
+            bit.set("load", 1)?;
+            bit.step();
+            bit.get("out")
+        })
+        .unwrap();
+        assert!(report
+            .undetected
+            .iter()
+            .any(|fault| fault.to_string() == "load stuck-at-1"));
+        assert!(report
+            .detected
+            .iter()
+            .any(|fault| fault.to_string() == "dffout stuck-at-0"));
+    }
+}
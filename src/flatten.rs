@@ -0,0 +1,779 @@
+//! Expansion of chip hierarchies into flat netlists of primitives
+//!
+//! Every part is expanded recursively until only [Primitive]s remain.  Each bit of every bus becomes a [Net];
+//! part connections merge nets together, so a flattened netlist has exactly one net per electrically distinct
+//! wire.  Instances are named hierarchically after the parts that created them, e.g.
+//! `ALU/Add16/FullAdder[3]/HalfAdder[1]/Xor/Nand[2]`, where the index is only present when a chip uses the same
+//! part more than once.
+
+use crate::limits::{Limit, Limits};
+use crate::project::{Project, Resolved};
+use crate::simulator::SimulationError;
+use crate::{Chip, Pin};
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::time::Instant;
+
+/// Identifier of a single bit wide wire in a [Netlist]
+pub type Net = usize;
+
+/// The net that is always false
+pub const FALSE: Net = 0;
+
+/// The net that is always true
+pub const TRUE: Net = 1;
+
+/// A chip which is implemented natively rather than in HDL
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Primitive {
+    /// `Nand(a, b; out)`
+    Nand,
+    /// `DFF(in; out)`, a data flip-flop
+    DFF,
+    /// `RAM16K(in[16], load, address[14]; out[16])`
+    RAM16K,
+    /// `ROM32K(address[15]; out[16])`
+    ROM32K,
+    /// `Screen(in[16], load, address[13]; out[16])`, the memory mapped display
+    Screen,
+    /// `Keyboard(; out[16])`, the memory mapped keyboard register
+    Keyboard,
+}
+
+impl Primitive {
+    /// Every primitive
+    pub const ALL: [Primitive; 6] = [
+        Primitive::Nand,
+        Primitive::DFF,
+        Primitive::RAM16K,
+        Primitive::ROM32K,
+        Primitive::Screen,
+        Primitive::Keyboard,
+    ];
+
+    /// Name of the primitive as used in HDL
+    pub fn name(self) -> &'static str {
+        match self {
+            Primitive::Nand => "Nand",
+            Primitive::DFF => "DFF",
+            Primitive::RAM16K => "RAM16K",
+            Primitive::ROM32K => "ROM32K",
+            Primitive::Screen => "Screen",
+            Primitive::Keyboard => "Keyboard",
+        }
+    }
+
+    /// Look up a primitive by its HDL name
+    pub fn from_name(name: &str) -> Option<Primitive> {
+        Primitive::ALL.iter().copied().find(|p| p.name() == name)
+    }
+
+    /// Input pins and their widths, in the order [Cell::inputs] stores them
+    pub fn inputs(self) -> &'static [(&'static str, usize)] {
+        match self {
+            Primitive::Nand => &[("a", 1), ("b", 1)],
+            Primitive::DFF => &[("in", 1)],
+            Primitive::RAM16K => &[("in", 16), ("load", 1), ("address", 14)],
+            Primitive::ROM32K => &[("address", 15)],
+            Primitive::Screen => &[("in", 16), ("load", 1), ("address", 13)],
+            Primitive::Keyboard => &[],
+        }
+    }
+
+    /// Output pins and their widths, in the order [Cell::outputs] stores them
+    pub fn outputs(self) -> &'static [(&'static str, usize)] {
+        match self {
+            Primitive::Nand | Primitive::DFF => &[("out", 1)],
+            _ => &[("out", 16)],
+        }
+    }
+
+    /// Whether the primitive only changes state on a clock edge
+    pub fn is_clocked(self) -> bool {
+        matches!(self, Primitive::DFF | Primitive::RAM16K | Primitive::Screen)
+    }
+
+    /// Indices of the input pins whose values reach the outputs without waiting for a clock edge
+    ///
+    /// Memories read combinationally from `address`, while `in` and `load` only matter when the clock ticks.
+    pub fn combinational_inputs(self) -> &'static [usize] {
+        match self {
+            Primitive::Nand => &[0, 1],
+            Primitive::RAM16K | Primitive::Screen => &[2],
+            Primitive::ROM32K => &[0],
+            Primitive::DFF | Primitive::Keyboard => &[],
+        }
+    }
+}
+
+/// A named group of nets, such as a chip's `a[16]` input
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Bus {
+    /// Holds the name of the bus
+    pub name: String,
+    /// Holds the nets of the bus, least significant bit first
+    pub nets: Vec<Net>,
+}
+
+/// A single primitive instance in a [Netlist]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Cell {
+    /// Holds the primitive implementing this cell
+    pub primitive: Primitive,
+    /// Holds the hierarchical instance name of the cell
+    pub path: String,
+    /// Holds the nets of each input pin, ordered as [Primitive::inputs]
+    pub inputs: Vec<Vec<Net>>,
+    /// Holds the nets of each output pin, ordered as [Primitive::outputs]
+    pub outputs: Vec<Vec<Net>>,
+}
+
+/// A chip instance that was expanded while flattening
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Instance {
+    /// Holds the hierarchical instance name
+    pub path: String,
+    /// Holds the name of the chip that was instantiated
+    pub chip: String,
+}
+
+/// A chip hierarchy expanded down to primitives
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Netlist {
+    /// Holds the name of the top level chip
+    pub name: String,
+    /// Holds the number of distinct nets, including [FALSE] and [TRUE]
+    pub nets: usize,
+    /// Holds the input buses of the top level chip
+    pub inputs: Vec<Bus>,
+    /// Holds the output buses of the top level chip
+    pub outputs: Vec<Bus>,
+    /// Holds every primitive instance
+    pub cells: Vec<Cell>,
+    /// Holds every HDL chip instance, starting with the top level chip
+    pub instances: Vec<Instance>,
+    /// Holds the nets of every pin and internal wire, keyed by hierarchical path such as `ALU/Add16/out`
+    pub wires: BTreeMap<String, Vec<Net>>,
+}
+
+impl Netlist {
+    /// Look up the nets of a pin or wire by hierarchical path
+    ///
+    /// The path may include the name of the top level chip (`CPU/ALU/zx`) or leave it out (`ALU/zx`).
+    pub fn wire(&self, path: &str) -> Option<&[Net]> {
+        self.wires
+            .get(path)
+            .or_else(|| self.wires.get(&format!("{}/{}", self.name, path)))
+            .map(Vec::as_slice)
+    }
+
+    /// Look up a top level input bus by name
+    pub fn input(&self, name: &str) -> Option<&Bus> {
+        self.inputs.iter().find(|bus| bus.name == name)
+    }
+
+    /// Look up a top level output bus by name
+    pub fn output(&self, name: &str) -> Option<&Bus> {
+        self.outputs.iter().find(|bus| bus.name == name)
+    }
+
+    /// Number of cells implemented by a primitive
+    pub fn count(&self, primitive: Primitive) -> usize {
+        self.cells
+            .iter()
+            .filter(|cell| cell.primitive == primitive)
+            .count()
+    }
+
+    /// Order the cells so that every cell comes after the cells driving its combinational inputs
+    ///
+    /// Fails if the netlist contains a combinational loop, i.e. a cycle that does not pass through a clocked
+    /// primitive.
+    pub fn topological_order(&self) -> Result<Vec<usize>, FlattenError> {
+        let mut driver = vec![None; self.nets];
+        for (index, cell) in self.cells.iter().enumerate() {
+            for &net in cell.outputs.iter().flatten() {
+                driver[net] = Some(index);
+            }
+        }
+        let mut dependents = vec![vec![]; self.cells.len()];
+        let mut pending = vec![0; self.cells.len()];
+        for (index, cell) in self.cells.iter().enumerate() {
+            for &pin in cell.primitive.combinational_inputs() {
+                for &net in &cell.inputs[pin] {
+                    if let Some(source) = driver[net] {
+                        dependents[source].push(index);
+                        pending[index] += 1;
+                    }
+                }
+            }
+        }
+
+        let mut order: Vec<usize> = (0..self.cells.len())
+            .filter(|&index| pending[index] == 0)
+            .collect();
+        let mut next = 0;
+        while next < order.len() {
+            for &dependent in &dependents[order[next]] {
+                pending[dependent] -= 1;
+                if pending[dependent] == 0 {
+                    order.push(dependent);
+                }
+            }
+            next += 1;
+        }
+        match pending.iter().position(|&count| count > 0) {
+            Some(index) => Err(FlattenError::new(&format!(
+                "{}: combinational loop through {}",
+                self.name, self.cells[index].path
+            ))),
+            None => Ok(order),
+        }
+    }
+}
+
+/// Error returned when a chip hierarchy cannot be flattened
+#[derive(Debug, Clone)]
+pub struct FlattenError {
+    details: String,
+}
+
+impl FlattenError {
+    pub(crate) fn new(msg: &str) -> FlattenError {
+        FlattenError {
+            details: msg.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for FlattenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.details)
+    }
+}
+
+impl Error for FlattenError {
+    fn description(&self) -> &str {
+        &self.details
+    }
+}
+
+/// Pins of a part and their widths, whether the part is a chip or a primitive
+struct Interface {
+    inputs: Vec<(String, usize)>,
+    outputs: Vec<(String, usize)>,
+}
+
+impl Interface {
+    fn of(resolved: Resolved<'_>) -> Interface {
+        fn pins(pins: &[Pin]) -> Vec<(String, usize)> {
+            pins.iter()
+                .map(|pin| (pin.name.clone(), pin.declared_width()))
+                .collect()
+        }
+        fn static_pins(pins: &[(&str, usize)]) -> Vec<(String, usize)> {
+            pins.iter()
+                .map(|&(name, width)| (name.to_string(), width))
+                .collect()
+        }
+        match resolved {
+            Resolved::Chip(chip) => Interface {
+                inputs: pins(&chip.inputs),
+                outputs: pins(&chip.outputs),
+            },
+            Resolved::Primitive(primitive) => Interface {
+                inputs: static_pins(primitive.inputs()),
+                outputs: static_pins(primitive.outputs()),
+            },
+        }
+    }
+
+    /// Width of a pin and whether it is an input
+    fn pin(&self, name: &str) -> Option<(bool, usize)> {
+        self.inputs
+            .iter()
+            .map(|(pin, width)| (pin, true, *width))
+            .chain(self.outputs.iter().map(|(pin, width)| (pin, false, *width)))
+            .find(|(pin, _, _)| *pin == name)
+            .map(|(_, input, width)| (input, width))
+    }
+}
+
+/// Something that drives the value of a net
+#[derive(Debug, Clone, Copy)]
+enum Driver {
+    Constant,
+    Input(usize),
+    Cell(usize),
+}
+
+struct Builder<'a> {
+    project: &'a Project,
+    parent: Vec<Net>,
+    drivers: Vec<(Net, Driver)>,
+    cells: Vec<Cell>,
+    instances: Vec<Instance>,
+    wires: Vec<(String, Vec<Net>)>,
+    stack: Vec<String>,
+    limits: Limits,
+    /// when expansion began, for the time limit
+    started: Instant,
+    exceeded: Option<Limit>,
+}
+
+impl<'a> Builder<'a> {
+    fn net(&mut self) -> Net {
+        self.parent.push(self.parent.len());
+        self.parent.len() - 1
+    }
+
+    fn bus(&mut self, width: usize) -> Vec<Net> {
+        (0..width).map(|_| self.net()).collect()
+    }
+
+    fn find(&mut self, net: Net) -> Net {
+        let mut root = net;
+        while self.parent[root] != root {
+            root = self.parent[root];
+        }
+        let mut net = net;
+        while self.parent[net] != root {
+            let next = self.parent[net];
+            self.parent[net] = root;
+            net = next;
+        }
+        root
+    }
+
+    fn union(&mut self, a: Net, b: Net) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a < b {
+            self.parent[b] = a;
+        } else {
+            self.parent[a] = b;
+        }
+    }
+
+    /// Record that a limit was hit, returning the error that stops the expansion
+    fn exceed(&mut self, limit: Limit) -> FlattenError {
+        self.exceeded = Some(limit);
+        FlattenError::new(&limit.to_string())
+    }
+
+    /// Expand a chip whose pins have already been allocated nets
+    fn expand(
+        &mut self,
+        chip: &'a Chip,
+        path: &str,
+        pins: BTreeMap<String, Vec<Net>>,
+    ) -> Result<(), FlattenError> {
+        if self.stack.contains(&chip.name) {
+            return Err(FlattenError::new(&format!(
+                "{}: chip {} instantiates itself",
+                path, chip.name
+            )));
+        }
+        if let Some(depth) = self.limits.depth {
+            if self.stack.len() >= depth && !chip.parts.is_empty() {
+                return Err(self.exceed(Limit::Depth(depth)));
+            }
+        }
+        if let Err(SimulationError::LimitExceeded(limit)) = self.limits.check_time(self.started) {
+            return Err(self.exceed(limit));
+        }
+        self.stack.push(chip.name.clone());
+        self.instances.push(Instance {
+            path: path.to_string(),
+            chip: chip.name.clone(),
+        });
+
+        let mut parts = Vec::with_capacity(chip.parts.len());
+        for part in &chip.parts {
+            let resolved = self.project.resolve(&part.name).ok_or_else(|| {
+                FlattenError::new(&format!("{}: unknown chip {}", path, part.name))
+            })?;
+            parts.push((resolved, Interface::of(resolved)));
+        }
+
+        // internal wires take their width from the part outputs that drive them
+        let mut widths: BTreeMap<&str, usize> = BTreeMap::new();
+        for (part, (_, interface)) in chip.parts.iter().zip(&parts) {
+            for (internal, external) in part.internal.iter().zip(&part.external) {
+                if let Some((false, width)) = interface.pin(&internal.name) {
+                    if pins.contains_key(&external.name) {
+                        continue;
+                    }
+                    let width = match (external.range(), internal.range()) {
+                        (Some((_, end)), _) => end + 1,
+                        (None, Some((start, end))) => end.saturating_sub(start) + 1,
+                        (None, None) => width,
+                    };
+                    let entry = widths.entry(&external.name).or_insert(0);
+                    *entry = (*entry).max(width);
+                }
+            }
+        }
+        let inputs: HashSet<&str> = chip.inputs.iter().map(|pin| pin.name.as_str()).collect();
+        let mut scope = pins;
+        for (name, width) in widths {
+            let bus = self.bus(width);
+            let _ = scope.insert(name.to_string(), bus);
+        }
+        for (name, nets) in &scope {
+            self.wires
+                .push((format!("{}/{}", path, name), nets.clone()));
+        }
+
+        for (index, (part, (resolved, interface))) in chip.parts.iter().zip(parts).enumerate() {
+            let instance = if chip.parts.iter().filter(|p| p.name == part.name).count() > 1 {
+                let occurrence = chip.parts[..index]
+                    .iter()
+                    .filter(|p| p.name == part.name)
+                    .count();
+                format!("{}/{}[{}]", path, part.name, occurrence)
+            } else {
+                format!("{}/{}", path, part.name)
+            };
+
+            let mut part_pins = BTreeMap::new();
+            for (name, width) in interface.inputs.iter().chain(&interface.outputs) {
+                let bus = self.bus(*width);
+                let _ = part_pins.insert(name.clone(), bus);
+            }
+            let mut connected = HashSet::new();
+            for (internal, external) in part.internal.iter().zip(&part.external) {
+                let (is_input, _) = interface.pin(&internal.name).ok_or_else(|| {
+                    FlattenError::new(&format!(
+                        "{}: {} has no pin named {}",
+                        instance, part.name, internal.name
+                    ))
+                })?;
+                let bits = select(&part_pins[&internal.name], internal, &instance)?;
+                match external.name.as_str() {
+                    constant @ "true" | constant @ "false" => {
+                        if !is_input {
+                            return Err(FlattenError::new(&format!(
+                                "{}: output pin {} cannot be connected to {}",
+                                instance, internal.name, constant
+                            )));
+                        }
+                        let net = if constant == "true" { TRUE } else { FALSE };
+                        for bit in bits {
+                            self.union(bit, net);
+                            let _ = connected.insert(bit);
+                        }
+                    }
+                    name => {
+                        let outer = scope.get(name).ok_or_else(|| {
+                            FlattenError::new(&format!("{}: undefined pin name {}", path, name))
+                        })?;
+                        if !is_input && inputs.contains(name) {
+                            return Err(FlattenError::new(&format!(
+                                "{}: cannot assign to input pin {}",
+                                path, name
+                            )));
+                        }
+                        let outer = select(outer, external, path)?;
+                        if outer.len() != bits.len() {
+                            return Err(FlattenError::new(&format!(
+                                "{}: width mismatch connecting {} ({} bits) to {} ({} bits)",
+                                instance,
+                                internal.name,
+                                bits.len(),
+                                name,
+                                outer.len()
+                            )));
+                        }
+                        for (bit, net) in bits.into_iter().zip(outer) {
+                            self.union(bit, net);
+                            let _ = connected.insert(bit);
+                        }
+                    }
+                }
+            }
+            // unconnected inputs read as false
+            for (name, _) in &interface.inputs {
+                for &bit in &part_pins[name] {
+                    if !connected.contains(&bit) {
+                        self.union(bit, FALSE);
+                    }
+                }
+            }
+
+            match resolved {
+                Resolved::Chip(definition) => self.expand(definition, &instance, part_pins)?,
+                Resolved::Primitive(primitive) => {
+                    let cell = self.cells.len();
+                    if let Some(gates) = self.limits.gates.filter(|&gates| cell >= gates) {
+                        return Err(self.exceed(Limit::Gates(gates)));
+                    }
+                    let mut take = |pins: &[(&str, usize)]| -> Vec<Vec<Net>> {
+                        pins.iter()
+                            .map(|(name, _)| part_pins.remove(*name).unwrap_or_default())
+                            .collect()
+                    };
+                    let inputs = take(primitive.inputs());
+                    let outputs = take(primitive.outputs());
+                    for &net in outputs.iter().flatten() {
+                        self.drivers.push((net, Driver::Cell(cell)));
+                    }
+                    self.cells.push(Cell {
+                        primitive,
+                        path: instance,
+                        inputs,
+                        outputs,
+                    });
+                }
+            }
+        }
+
+        let _ = self.stack.pop();
+        Ok(())
+    }
+}
+
+/// Select the bits of a bus addressed by a pin in a part connection
+fn select(nets: &[Net], pin: &Pin, path: &str) -> Result<Vec<Net>, FlattenError> {
+    match pin.range() {
+        None => Ok(nets.to_vec()),
+        Some((start, end)) if start <= end && end < nets.len() => Ok(nets[start..=end].to_vec()),
+        Some((start, end)) => Err(FlattenError::new(&format!(
+            "{}: {}[{}..{}] is out of range for a {} bit bus",
+            path,
+            pin.name,
+            start,
+            end,
+            nets.len()
+        ))),
+    }
+}
+
+/// Expand a chip and every part beneath it into a [Netlist] of primitives
+pub fn flatten(chip: &Chip, project: &Project) -> Result<Netlist, FlattenError> {
+    build(chip, project, Limits::default()).map_err(|(error, _)| error)
+}
+
+/// Expand a chip like [flatten], giving up once the hierarchy is deeper, has more gates or takes longer to expand
+/// than `limits` allow
+///
+/// The cycle limit does not apply to flattening.
+pub fn flatten_with_limits(
+    chip: &Chip,
+    project: &Project,
+    limits: &Limits,
+) -> Result<Netlist, SimulationError> {
+    build(chip, project, *limits).map_err(|(error, exceeded)| match exceeded {
+        Some(limit) => SimulationError::LimitExceeded(limit),
+        None => SimulationError::Flatten(error),
+    })
+}
+
+/// Flatten a chip, pairing any error with the limit that stopped the expansion, if one did
+fn build(
+    chip: &Chip,
+    project: &Project,
+    limits: Limits,
+) -> Result<Netlist, (FlattenError, Option<Limit>)> {
+    let mut builder = Builder {
+        project,
+        parent: vec![FALSE, TRUE],
+        drivers: vec![(FALSE, Driver::Constant), (TRUE, Driver::Constant)],
+        cells: vec![],
+        instances: vec![],
+        wires: vec![],
+        stack: vec![],
+        limits,
+        started: Instant::now(),
+        exceeded: None,
+    };
+
+    let mut pins = BTreeMap::new();
+    let mut inputs = vec![];
+    let mut outputs = vec![];
+    for (index, pin) in chip.inputs.iter().enumerate() {
+        let bus = builder.bus(pin.declared_width());
+        for &net in &bus {
+            builder.drivers.push((net, Driver::Input(index)));
+        }
+        let _ = pins.insert(pin.name.clone(), bus.clone());
+        inputs.push((pin.name.clone(), bus));
+    }
+    for pin in &chip.outputs {
+        let bus = builder.bus(pin.declared_width());
+        let _ = pins.insert(pin.name.clone(), bus.clone());
+        outputs.push((pin.name.clone(), bus));
+    }
+    if let Err(error) = builder.expand(chip, &chip.name, pins) {
+        return Err((error, builder.exceeded));
+    }
+
+    // number the merged nets densely, keeping FALSE and TRUE first
+    let mut ids = vec![usize::MAX; builder.parent.len()];
+    let mut nets = 0;
+    for net in 0..builder.parent.len() {
+        let root = builder.find(net);
+        if ids[root] == usize::MAX {
+            ids[root] = nets;
+            nets += 1;
+        }
+        ids[net] = ids[root];
+    }
+
+    let mut driven: Vec<Option<Driver>> = vec![None; nets];
+    for &(net, driver) in &builder.drivers {
+        if let Some(existing) = driven[ids[net]] {
+            let describe = |driver: Driver| match driver {
+                Driver::Constant => "a constant".to_string(),
+                Driver::Input(index) => format!("input {}", chip.inputs[index].name),
+                Driver::Cell(cell) => builder.cells[cell].path.clone(),
+            };
+            return Err((
+                FlattenError::new(&format!(
+                    "{}: a wire is driven by both {} and {}",
+                    chip.name,
+                    describe(existing),
+                    describe(driver)
+                )),
+                None,
+            ));
+        }
+        driven[ids[net]] = Some(driver);
+    }
+
+    let remap = |bus: Vec<Net>| bus.into_iter().map(|net| ids[net]).collect::<Vec<_>>();
+    Ok(Netlist {
+        name: chip.name.clone(),
+        nets,
+        inputs: inputs
+            .into_iter()
+            .map(|(name, nets)| Bus {
+                name,
+                nets: remap(nets),
+            })
+            .collect(),
+        outputs: outputs
+            .into_iter()
+            .map(|(name, nets)| Bus {
+                name,
+                nets: remap(nets),
+            })
+            .collect(),
+        cells: builder
+            .cells
+            .into_iter()
+            .map(|cell| Cell {
+                inputs: cell.inputs.into_iter().map(remap).collect(),
+                outputs: cell.outputs.into_iter().map(remap).collect(),
+                ..cell
+            })
+            .collect(),
+        instances: builder.instances,
+        wires: builder
+            .wires
+            .into_iter()
+            .map(|(path, nets)| (path, remap(nets)))
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flatten::{flatten, Primitive, FALSE, TRUE};
+    use crate::{parse_hdl, Project};
+    use std::collections::HashSet;
+
+    #[test]
+    fn flattens_builtin_hierarchy() {
+        let project = Project::new();
+        let netlist = flatten(project.chip("Xor").unwrap(), &project).unwrap();
+        assert_eq!(netlist.count(Primitive::Nand), 4);
+        assert_eq!(netlist.cells[2].path, "Xor/Nand[2]");
+
+        let netlist = flatten(project.chip("Add16").unwrap(), &project).unwrap();
+        assert!(netlist
+            .cells
+            .iter()
+            .any(|cell| cell.path == "Add16/FullAdder[3]/HalfAdder[1]/Xor/Nand[2]"));
+        assert_eq!(netlist.input("a").unwrap().nets.len(), 16);
+    }
+
+    #[test]
+    fn connects_constants_and_slices() {
+        let project = Project::new();
+        let chip = parse_hdl(
+            "CHIP Test {
+                IN a[4];
+                OUT out[2];
+                PARTS:
+                Nand(a=a[3], b=true, out=out[0]);
+                Nand(a=false, out=out[1]);
+            }",
+        )
+        .unwrap();
+        let netlist = flatten(&chip, &project).unwrap();
+        assert_eq!(
+            netlist.cells[0].inputs,
+            vec![vec![netlist.inputs[0].nets[3]], vec![TRUE]]
+        );
+        assert_eq!(netlist.cells[1].inputs, vec![vec![FALSE], vec![FALSE]]);
+        assert_eq!(
+            netlist.wire("out").unwrap(),
+            netlist.outputs[0].nets.as_slice()
+        );
+    }
+
+    #[test]
+    fn orders_cells_topologically() {
+        let project = Project::new();
+        let netlist = flatten(project.chip("Bit").unwrap(), &project).unwrap();
+        let order = netlist.topological_order().unwrap();
+        assert_eq!(order.len(), netlist.cells.len());
+        let mut evaluated = HashSet::new();
+        for &index in &order {
+            let cell = &netlist.cells[index];
+            for &pin in cell.primitive.combinational_inputs() {
+                for net in &cell.inputs[pin] {
+                    let source = netlist
+                        .cells
+                        .iter()
+                        .position(|cell| cell.outputs.iter().flatten().any(|out| out == net));
+                    assert!(source.is_none_or(|source| evaluated.contains(&source)));
+                }
+            }
+            let _ = evaluated.insert(index);
+        }
+
+        let chip = parse_hdl(
+            "CHIP Loop { IN a; OUT out; PARTS: Nand(a=a, b=x, out=y); Nand(a=y, b=y, out=x, out=out); }",
+        )
+        .unwrap();
+        assert!(flatten(&chip, &project)
+            .unwrap()
+            .topological_order()
+            .is_err());
+    }
+
+    #[test]
+    fn reports_bad_hierarchies() {
+        let mut project = Project::new();
+        let _ = project
+            .add_hdl("CHIP Loop { IN a; OUT out; PARTS: Loop(a=a, out=out); }")
+            .unwrap();
+        let errors = [
+            "CHIP A { IN a; OUT out; PARTS: Missing(a=a, out=out); }",
+            "CHIP A { IN a; OUT out; PARTS: Not(in=b, out=out); }",
+            "CHIP A { IN a[2]; OUT out; PARTS: Not(in=a, out=out); }",
+            "CHIP A { IN a; OUT out; PARTS: Not(in=a, out=out); Not(in=a, out=out); }",
+            "CHIP A { IN a; OUT out; PARTS: Loop(a=a, out=out); }",
+        ];
+        for hdl in errors.iter() {
+            assert!(
+                flatten(&parse_hdl(hdl).unwrap(), &project).is_err(),
+                "{}",
+                hdl
+            );
+        }
+    }
+}
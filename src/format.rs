@@ -0,0 +1,307 @@
+//! Column layout of nand2tetris comparison and output files
+//!
+//! Every column is described like an `output-list` entry: `out%B1.16.1` prints `out` in binary, sixteen characters
+//! wide with one space of padding on either side.  A [Specifier] formats values and column names into exactly the
+//! cells the official simulator prints:
+//!
+//! ```
+//! use nand2tetris_hdl_parser::format::Specifier;
+//!
+//! let specifier: Specifier = "%D1.6.1".parse().unwrap();
+//! assert_eq!(specifier.format(0xffff, 16), "     -1 ");
+//! assert_eq!(specifier.title("out"), "  out   ");
+//! ```
+
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::str::FromStr;
+
+/// How an `output-list` column prints values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Radix {
+    /// `%B`, binary
+    Binary,
+    /// `%D`, signed decimal
+    Decimal,
+    /// `%X`, hexadecimal
+    Hex,
+    /// `%S`, a string such as the simulated time
+    String,
+}
+
+impl Radix {
+    /// The radix written as the given letter after `%`
+    pub fn from_letter(letter: char) -> Option<Radix> {
+        match letter {
+            'B' => Some(Radix::Binary),
+            'D' => Some(Radix::Decimal),
+            'X' => Some(Radix::Hex),
+            'S' => Some(Radix::String),
+            _ => None,
+        }
+    }
+
+    /// The letter written after `%` for the radix
+    pub fn letter(self) -> char {
+        match self {
+            Radix::Binary => 'B',
+            Radix::Decimal => 'D',
+            Radix::Hex => 'X',
+            Radix::String => 'S',
+        }
+    }
+}
+
+/// A format specifier such as `%B3.16.3`: the radix, then the padding left of the value, the width of the value
+/// and the padding right of it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Specifier {
+    /// Holds how values are printed
+    pub radix: Radix,
+    /// Holds the number of spaces before the value
+    pub left: usize,
+    /// Holds the number of characters the value takes
+    pub len: usize,
+    /// Holds the number of spaces after the value
+    pub right: usize,
+}
+
+impl Specifier {
+    /// A value of a bus `width` bits wide, padded as the specifier says
+    ///
+    /// Binary and hexadecimal values are zero padded to the full length, decimal values are right aligned and
+    /// 16 bit values printed in decimal are signed, as in the official simulator.  Values too long for the column
+    /// keep only their rightmost characters.
+    pub fn format(&self, value: u64, width: usize) -> String {
+        match self.radix {
+            Radix::Binary => self.pad(&format!("{:0len$b}", value, len = self.len), false),
+            Radix::Hex => self.pad(&format!("{:0len$X}", value, len = self.len), false),
+            Radix::Decimal if width == 16 => self.pad(&(value as u16 as i16).to_string(), false),
+            Radix::Decimal => self.pad(&value.to_string(), false),
+            Radix::String => self.pad(&value.to_string(), true),
+        }
+    }
+
+    /// A value in two's complement, so negative values are printed as the simulator would store them
+    ///
+    /// Only the lowest `width` bits of the value are kept.
+    pub fn format_signed(&self, value: i64, width: usize) -> String {
+        let mask = if width >= 64 {
+            u64::MAX
+        } else {
+            (1 << width) - 1
+        };
+        self.format(value as u64 & mask, width)
+    }
+
+    /// Text, such as the simulated time, padded as the specifier says
+    ///
+    /// Text is left aligned whatever the radix.
+    pub fn format_text(&self, text: &str) -> String {
+        self.pad(text, true)
+    }
+
+    /// The name of a column centred within the whole width of the column, cut short if it does not fit
+    ///
+    /// Names that cannot be centred exactly lean left.
+    pub fn title(&self, name: &str) -> String {
+        let space = self.left + self.len + self.right;
+        let name: String = name.chars().take(space).collect();
+        let left = (space - name.chars().count()) / 2;
+        let right = space - left - name.chars().count();
+        format!("{}{}{}", " ".repeat(left), name, " ".repeat(right))
+    }
+
+    fn pad(&self, text: &str, left_aligned: bool) -> String {
+        let chars: Vec<char> = text.chars().collect();
+        let text: String = chars[chars.len().saturating_sub(self.len)..]
+            .iter()
+            .collect();
+        let fill = self.len - text.chars().count();
+        let (left, right) = if left_aligned {
+            (self.left, fill + self.right)
+        } else {
+            (self.left + fill, self.right)
+        };
+        format!("{}{}{}", " ".repeat(left), text, " ".repeat(right))
+    }
+}
+
+impl fmt::Display for Specifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "%{}{}.{}.{}",
+            self.radix.letter(),
+            self.left,
+            self.len,
+            self.right
+        )
+    }
+}
+
+/// Error returned when text is not a format specifier
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SpecifierError {
+    /// Holds the text that could not be parsed
+    pub specifier: String,
+}
+
+impl fmt::Display for SpecifierError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid format specifier {}, expected %B, %D, %X or %S followed by three numbers like %B1.16.1",
+            self.specifier
+        )
+    }
+}
+
+impl Error for SpecifierError {}
+
+impl FromStr for Specifier {
+    type Err = SpecifierError;
+
+    /// Parse a specifier like `%B3.16.3`, with or without the `%`
+    fn from_str(text: &str) -> Result<Specifier, SpecifierError> {
+        let error = || SpecifierError {
+            specifier: text.to_string(),
+        };
+        let spec = text.strip_prefix('%').unwrap_or(text);
+        let mut chars = spec.chars();
+        let radix = chars
+            .next()
+            .and_then(Radix::from_letter)
+            .ok_or_else(error)?;
+        let numbers = chars
+            .as_str()
+            .split('.')
+            .map(|number| {
+                // only plain digits, which rules out signs
+                if !number.chars().all(|c| c.is_ascii_digit()) {
+                    return Err(error());
+                }
+                number.parse().map_err(|_| error())
+            })
+            .collect::<Result<Vec<usize>, _>>()?;
+        match numbers[..] {
+            [left, len, right] => Ok(Specifier {
+                radix,
+                left,
+                len,
+                right,
+            }),
+            _ => Err(error()),
+        }
+    }
+}
+
+/// A single column of a comparison or output file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Column {
+    pub(crate) name: String,
+    pub(crate) format: Specifier,
+    pub(crate) width: usize,
+}
+
+impl Column {
+    /// The column the official test scripts use for a pin of the given width
+    ///
+    /// Single bits are printed as `%B3.1.3` and buses as `%B1.<width>.1`.
+    pub(crate) fn for_pin(name: &str, width: usize) -> Column {
+        let (left, right) = if width == 1 { (3, 3) } else { (1, 1) };
+        Column {
+            name: name.to_string(),
+            format: Specifier {
+                radix: Radix::Binary,
+                left,
+                len: width,
+                right,
+            },
+            width,
+        }
+    }
+}
+
+/// The header line naming every column, each name centred within its column
+pub(crate) fn header(columns: &[Column]) -> String {
+    let mut line = String::from("|");
+    for column in columns {
+        line.push_str(&column.format.title(&column.name));
+        line.push('|');
+    }
+    line
+}
+
+/// A line printing one value per column
+pub(crate) fn row(columns: &[Column], values: &[u64]) -> String {
+    let mut line = String::from("|");
+    for (column, &value) in columns.iter().zip(values) {
+        line.push_str(&column.format.format(value, column.width));
+        line.push('|');
+    }
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{Radix, Specifier};
+
+    fn specifier(text: &str) -> Specifier {
+        text.parse().unwrap()
+    }
+
+    #[test]
+    fn formats_radixes() {
+        assert_eq!(specifier("%B3.1.3").format(1, 1), "   1   ");
+        assert_eq!(specifier("%B1.16.1").format(5, 16), " 0000000000000101 ");
+        assert_eq!(specifier("%D1.6.1").format(0xffff, 16), "     -1 ");
+        assert_eq!(specifier("%D1.6.1").format(7, 3), "      7 ");
+        assert_eq!(specifier("%D1.6.1").format_signed(-32768, 16), " -32768 ");
+        assert_eq!(specifier("%X2.4.2").format(0x1f, 16), "  001F  ");
+        assert_eq!(specifier("%S1.5.1").format_text("12+"), " 12+   ");
+        assert_eq!(specifier("%S1.5.1").format(12, 16), " 12    ");
+        assert_eq!(specifier("%B1.4.1").format_signed(-1, 4), " 1111 ");
+    }
+
+    #[test]
+    fn truncates_to_width() {
+        assert_eq!(specifier("%B1.4.1").format(0xabcd, 16), " 1101 ");
+        assert_eq!(specifier("%D0.3.0").format(12345, 16), "345");
+        assert_eq!(specifier("%S0.2.0").format_text("100+"), "0+");
+        assert_eq!(specifier("%D1.6.1").title("DRegister[]"), "DRegiste");
+        assert_eq!(specifier("%B3.1.3").title("out"), "  out  ");
+        assert_eq!(specifier("%D1.6.1").title("in"), "   in   ");
+    }
+
+    #[test]
+    fn parses_specifiers() {
+        assert_eq!(
+            specifier("%B3.16.3"),
+            Specifier {
+                radix: Radix::Binary,
+                left: 3,
+                len: 16,
+                right: 3
+            }
+        );
+        assert_eq!(specifier("X2.4.2").radix, Radix::Hex);
+        assert_eq!(specifier("%S1.5.1").to_string(), "%S1.5.1");
+        for invalid in &[
+            "",
+            "%Q1.1.1",
+            "%B1.1",
+            "%B1.1.1.1",
+            "%B1.-1.1",
+            "%B+1.1.1",
+            "%B1..1",
+        ] {
+            assert!(invalid.parse::<Specifier>().is_err(), "{}", invalid);
+        }
+        assert_eq!(
+            "%B1.1".parse::<Specifier>().unwrap_err().to_string(),
+            "invalid format specifier %B1.1, expected %B, %D, %X or %S followed by three numbers like %B1.16.1"
+        );
+    }
+}
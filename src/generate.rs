@@ -0,0 +1,268 @@
+//! Generating test scripts and comparison files from a reference chip
+//!
+//! The script sets the inputs of the chip to each chosen vector in turn and outputs every pin, clocking sequential
+//! chips once per vector.  The comparison file is the output of running that script against the reference, so a
+//! chip passes the generated test exactly when it behaves like the reference on those vectors.
+
+use crate::flatten::{flatten, Bus};
+use crate::format::Column;
+use crate::random::Rng;
+use crate::runner::run_script;
+use crate::simulator::SimulationError;
+use crate::test_script::parse_tst;
+use crate::truth_table::MAX_INPUT_BITS;
+use crate::{Chip, Project};
+use serde::{Deserialize, Serialize};
+
+/// Which input vectors a generated test applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Vectors {
+    /// Every combination of every input, the first input changing slowest
+    Exhaustive,
+    /// Zero, one, all ones, the top bit alone and alternating bits on each input in turn, with the other inputs
+    /// all zeros and then all ones
+    Corners,
+    /// Inputs drawn at random from a seed
+    Random {
+        /// Holds the number of vectors
+        count: usize,
+        /// Holds the seed the vectors are drawn from
+        seed: u64,
+    },
+}
+
+/// A generated test script and the comparison file it is checked against
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GeneratedTest {
+    /// Holds the contents of the `.tst` file
+    pub tst: String,
+    /// Holds the contents of the `.cmp` file
+    pub cmp: String,
+}
+
+/// Generate a `.tst` script applying input vectors to a chip, and the `.cmp` file the reference chip produces
+///
+/// Columns are printed in binary, `%B3.1.3` for single bits and `%B1.<width>.1` for buses, after a `time` column
+/// for sequential chips.  Exhaustive vectors are limited to [MAX_INPUT_BITS] input bits in total.
+pub fn generate_test(
+    reference: &Chip,
+    project: &Project,
+    vectors: Vectors,
+) -> Result<GeneratedTest, SimulationError> {
+    let netlist = flatten(reference, project)?;
+    let clocked = netlist.cells.iter().any(|cell| cell.primitive.is_clocked());
+    let name = &netlist.name;
+
+    let mut tst = format!(
+        "// Generated from the reference {} chip\n\n\
+         load {}.hdl,\n\
+         output-file {}.out,\n\
+         compare-to {}.cmp,\n\
+         output-list",
+        name, name, name, name
+    );
+    if clocked {
+        tst.push_str(" time%S1.4.1");
+    }
+    for bus in netlist.inputs.iter().chain(&netlist.outputs) {
+        let column = Column::for_pin(&bus.name, bus.nets.len());
+        tst.push_str(&format!(" {}{}", column.name, column.format));
+    }
+    tst.push_str(";\n\n");
+
+    for vector in input_vectors(&netlist.inputs, vectors)? {
+        let mut commands: Vec<String> = netlist
+            .inputs
+            .iter()
+            .zip(vector)
+            .map(|(bus, value)| format!("set {} {}", bus.name, constant(value, bus.nets.len())))
+            .collect();
+        if clocked {
+            commands.extend(
+                vec!["tick", "output", "tock", "output"]
+                    .into_iter()
+                    .map(String::from),
+            );
+        } else {
+            commands.extend(vec!["eval", "output"].into_iter().map(String::from));
+        }
+        tst.push_str(&commands.join(", "));
+        tst.push_str(";\n");
+    }
+
+    let script = parse_tst(&tst).map_err(|e| SimulationError::InvalidScript(e.to_string()))?;
+    let cmp = run_script(&script, reference, project, None)?.output;
+    Ok(GeneratedTest { tst, cmp })
+}
+
+/// A value written for `set`, in binary for buses
+fn constant(value: u64, width: usize) -> String {
+    if width == 1 {
+        value.to_string()
+    } else {
+        format!("%B{:0width$b}", value, width = width)
+    }
+}
+
+fn input_vectors(inputs: &[Bus], vectors: Vectors) -> Result<Vec<Vec<u64>>, SimulationError> {
+    let widths: Vec<usize> = inputs.iter().map(|bus| bus.nets.len()).collect();
+    let ones = |width: usize| {
+        if width >= 64 {
+            u64::MAX
+        } else {
+            (1 << width) - 1
+        }
+    };
+    match vectors {
+        Vectors::Exhaustive => {
+            let bits: usize = widths.iter().sum();
+            if bits > MAX_INPUT_BITS {
+                return Err(SimulationError::TooManyInputs(bits));
+            }
+            Ok((0..1u64 << bits)
+                .map(|combination| {
+                    let mut shift = bits;
+                    widths
+                        .iter()
+                        .map(|&width| {
+                            shift -= width;
+                            combination >> shift & ones(width)
+                        })
+                        .collect()
+                })
+                .collect())
+        }
+        Vectors::Corners => {
+            let zeros = vec![0; widths.len()];
+            let all: Vec<u64> = widths.iter().map(|&width| ones(width)).collect();
+            let mut vectors = vec![zeros.clone(), all.clone()];
+            for (index, &width) in widths.iter().enumerate() {
+                let alternating = 0x5555_5555_5555_5555 & ones(width);
+                let corners = [
+                    0,
+                    1,
+                    ones(width),
+                    1 << (width.max(1) - 1),
+                    alternating,
+                    !alternating & ones(width),
+                ];
+                for background in [&zeros, &all].iter() {
+                    for &corner in &corners {
+                        let mut vector = background.to_vec();
+                        vector[index] = corner;
+                        if !vectors.contains(&vector) {
+                            vectors.push(vector);
+                        }
+                    }
+                }
+            }
+            Ok(vectors)
+        }
+        Vectors::Random { count, seed } => {
+            let mut rng = Rng::new(seed);
+            let mut vectors = Vec::with_capacity(count);
+            for _ in 0..count {
+                vectors.push(widths.iter().map(|&width| rng.bits(width)).collect());
+            }
+            Ok(vectors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compare::{compare, Table};
+    use crate::generate::{generate_test, Vectors};
+    use crate::runner::run_script;
+    use crate::simulator::SimulationError;
+    use crate::test_script::parse_tst;
+    use crate::Project;
+
+    #[test]
+    fn generates_exhaustive_tests() {
+        let project = Project::new();
+        let xor = project.chip("Xor").unwrap();
+        let test = generate_test(xor, &project, Vectors::Exhaustive).unwrap();
+        assert_eq!(
+            test.tst,
+            "// Generated from the reference Xor chip\n\n\
+             load Xor.hdl,\n\
+             output-file Xor.out,\n\
+             compare-to Xor.cmp,\n\
+             output-list a%B3.1.3 b%B3.1.3 out%B3.1.3;\n\n\
+             set a 0, set b 0, eval, output;\n\
+             set a 0, set b 1, eval, output;\n\
+             set a 1, set b 0, eval, output;\n\
+             set a 1, set b 1, eval, output;\n"
+        );
+        assert_eq!(
+            test.cmp,
+            "|   a   |   b   |  out  |\n\
+             |   0   |   0   |   0   |\n\
+             |   0   |   1   |   1   |\n\
+             |   1   |   0   |   1   |\n\
+             |   1   |   1   |   0   |\n"
+        );
+        let alu = project.chip("ALU").unwrap();
+        assert!(matches!(
+            generate_test(alu, &project, Vectors::Exhaustive),
+            Err(SimulationError::TooManyInputs(38))
+        ));
+    }
+
+    #[test]
+    fn generates_corner_cases() {
+        let project = Project::new();
+        let add = project.chip("Add16").unwrap();
+        let test = generate_test(add, &project, Vectors::Corners).unwrap();
+        assert!(test
+            .tst
+            .contains("set a %B1111111111111111, set b %B0000000000000001, eval, output;\n"));
+        assert!(test
+            .cmp
+            .contains("| 1111111111111111 | 0000000000000001 | 0000000000000000 |\n"));
+        // every vector is distinct
+        let lines: Vec<&str> = test
+            .tst
+            .lines()
+            .filter(|line| line.starts_with("set"))
+            .collect();
+        let mut unique = lines.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(lines.len(), unique.len());
+    }
+
+    #[test]
+    fn checks_chips_against_reference() {
+        let mut project = Project::new();
+        let reference = project.chip("Bit").unwrap().clone();
+        let test =
+            generate_test(&reference, &project, Vectors::Random { count: 20, seed: 3 }).unwrap();
+        assert!(test
+            .tst
+            .contains("output-list time%S1.4.1 in%B3.1.3 load%B3.1.3 out%B3.1.3;"));
+        assert_eq!(test.cmp.lines().count(), 41);
+
+        // a bit that ignores load fails the generated test
+        let broken = project
+            .add_hdl(
+                "CHIP Bit {
+                    IN in, load;
+                    OUT out;
+                    PARTS:
+                    DFF(in=in, out=out);
+                }",
+            )
+            .unwrap()
+            .clone();
+        let script = parse_tst(&test.tst).unwrap();
+        let output = run_script(&script, &broken, &project, None).unwrap().output;
+        let expected = Table::parse(&test.cmp).unwrap();
+        assert!(compare(&Table::parse(&output).unwrap(), &expected).is_some());
+        let output = run_script(&script, &reference, &project, None)
+            .unwrap()
+            .output;
+        assert_eq!(compare(&Table::parse(&output).unwrap(), &expected), None);
+    }
+}
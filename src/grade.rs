@@ -0,0 +1,371 @@
+//! Grading a project against its tests and a policy of allowed parts
+//!
+//! Courses restrict the parts each chip may be built from: in project 1 only `Nand` and chips built earlier, so a
+//! `Xor` may not simply use a builtin `And16`.  A [Policy] lists the allowed parts, and every part of a chip, and of
+//! the project's own chips it is built from, is checked against it.  Test results and violations are combined into a
+//! score per chip.
+//!
+//! Policies are written in JSON:
+//!
+//! ```json
+//! {
+//!     "allowed": ["Nand"],
+//!     "order": ["Not", "And", "Or", "Xor", "Mux", "DMux"],
+//!     "chips": { "Mux16": ["Not", "And", "Or", "Mux"] },
+//!     "points": { "Xor": 2 },
+//!     "penalty": 0.5,
+//!     "limits": { "cycles": 10000, "milliseconds": 5000 }
+//! }
+//! ```
+
+use crate::limits::Limits;
+use crate::simulator::SimulationError;
+use crate::suite::{run_project_tests_with_limits, Status};
+use crate::{Chip, Project};
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::path::Path;
+
+/// Which parts chips may use, and what each chip is worth
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Policy {
+    /// Holds parts every chip may use, or `None` to allow any part to chips without a rule
+    pub allowed: Option<Vec<String>>,
+    /// Holds chips in the order they are built; each may use the chips before it on top of `allowed`
+    pub order: Vec<String>,
+    /// Holds the exact parts allowed for particular chips, overriding `allowed` and `order`
+    pub chips: BTreeMap<String, Vec<String>>,
+    /// Holds what each chip is worth, one point unless listed
+    pub points: BTreeMap<String, f64>,
+    /// Holds the share of a chip's points lost to using a part that is not allowed, all of them if not given
+    pub penalty: Option<f64>,
+    /// Holds the limits each chip's test runs within
+    pub limits: Limits,
+}
+
+/// Error returned when a policy cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct PolicyError {
+    /// Holds what is wrong with the policy
+    pub message: String,
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid policy: {}", self.message)
+    }
+}
+
+impl Error for PolicyError {}
+
+impl Policy {
+    /// Parse a policy from JSON
+    pub fn parse(json: &str) -> Result<Policy, PolicyError> {
+        serde_json::from_str(json).map_err(|e| PolicyError {
+            message: e.to_string(),
+        })
+    }
+
+    /// Parts a chip may use, or `None` if it may use any
+    pub fn allowed(&self, chip: &str) -> Option<BTreeSet<&str>> {
+        if let Some(parts) = self.chips.get(chip) {
+            return Some(parts.iter().map(String::as_str).collect());
+        }
+        let position = self.order.iter().position(|name| name == chip);
+        if self.allowed.is_none() && position.is_none() {
+            return None;
+        }
+        let earlier = &self.order[..position.unwrap_or(0)];
+        Some(
+            self.allowed
+                .iter()
+                .flatten()
+                .chain(earlier)
+                .map(String::as_str)
+                .collect(),
+        )
+    }
+
+    /// Points a chip is worth
+    pub fn points(&self, chip: &str) -> f64 {
+        self.points.get(chip).copied().unwrap_or(1.0)
+    }
+}
+
+/// A part used by a chip whose policy does not allow it
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct Violation {
+    /// Holds the chip using the part
+    pub chip: String,
+    /// Holds the part that is not allowed
+    pub part: String,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} may not use {}", self.chip, self.part)
+    }
+}
+
+/// Every part in the hierarchy of a chip that its policy does not allow
+///
+/// The parts of the chip are checked, then the parts of each of the project's own chips it uses, each against its
+/// own rule.  Builtin chips are not looked into.
+pub fn check_policy(chip: &Chip, project: &Project, policy: &Policy) -> Vec<Violation> {
+    let own: BTreeMap<&str, &Chip> = project
+        .chips()
+        .map(|chip| (chip.name.as_str(), chip))
+        .collect();
+    let mut violations = BTreeSet::new();
+    let mut visited = BTreeSet::new();
+    let mut pending = vec![chip];
+    while let Some(chip) = pending.pop() {
+        if !visited.insert(chip.name.clone()) {
+            continue;
+        }
+        let allowed = policy.allowed(&chip.name);
+        for part in &chip.parts {
+            if allowed
+                .as_ref()
+                .is_some_and(|allowed| !allowed.contains(part.name.as_str()))
+            {
+                let _ = violations.insert(Violation {
+                    chip: chip.name.clone(),
+                    part: part.name.clone(),
+                });
+            }
+            if let Some(&sub) = own.get(part.name.as_str()) {
+                pending.push(sub);
+            }
+        }
+    }
+    violations.into_iter().collect()
+}
+
+/// The grade of one chip
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Grade {
+    /// Holds the name of the chip
+    pub chip: String,
+    /// Holds the outcome of its test
+    pub status: Status,
+    /// Holds what went wrong with its test, unless it passed
+    pub message: Option<String>,
+    /// Holds the parts it or its own parts use against the policy
+    pub violations: Vec<Violation>,
+    /// Holds the points scored
+    pub score: f64,
+    /// Holds the points the chip is worth
+    pub points: f64,
+}
+
+/// The grades of every tested chip of a project
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GradeReport {
+    /// Holds the name of the project directory
+    pub project: String,
+    /// Holds one grade per tested chip, ordered by name
+    pub grades: Vec<Grade>,
+}
+
+impl GradeReport {
+    /// Points scored over every chip
+    pub fn score(&self) -> f64 {
+        self.grades.iter().map(|grade| grade.score).sum()
+    }
+
+    /// Points every chip together is worth
+    pub fn points(&self) -> f64 {
+        self.grades.iter().map(|grade| grade.points).sum()
+    }
+
+    /// The report as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for GradeReport {
+    /// One line per chip with its score, test outcome and violations, followed by the total
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for grade in &self.grades {
+            write!(
+                f,
+                "{}: {}/{} {}",
+                grade.chip, grade.score, grade.points, grade.status
+            )?;
+            if let Some(message) = &grade.message {
+                write!(f, ": {}", message)?;
+            }
+            writeln!(f)?;
+            for violation in &grade.violations {
+                writeln!(f, "  {}", violation)?;
+            }
+        }
+        writeln!(f, "total: {}/{}", self.score(), self.points())
+    }
+}
+
+/// Run every test of a project directory and grade each tested chip against a policy
+///
+/// A chip scores its points if its test passes, less the policy's penalty if it breaks the policy, and nothing
+/// otherwise.
+pub fn grade_project<P: AsRef<Path>>(
+    directory: P,
+    policy: &Policy,
+) -> Result<GradeReport, SimulationError> {
+    let directory = directory.as_ref();
+    let report = run_project_tests_with_limits(directory, &policy.limits)?;
+    let project = Project::from_dir(directory)
+        .map_err(|e| SimulationError::Io(format!("cannot read {}: {}", directory.display(), e)))?;
+    let penalty = policy.penalty.unwrap_or(1.0).clamp(0.0, 1.0);
+    let grades = report
+        .results
+        .into_iter()
+        .map(|result| {
+            let violations = project
+                .chips()
+                .find(|chip| chip.name == result.chip)
+                .map(|chip| check_policy(chip, &project, policy))
+                .unwrap_or_default();
+            let points = policy.points(&result.chip);
+            let score = match result.status {
+                Status::Passed if violations.is_empty() => points,
+                Status::Passed => points * (1.0 - penalty),
+                _ => 0.0,
+            };
+            Grade {
+                chip: result.chip,
+                status: result.status,
+                message: result.message,
+                violations,
+                score,
+                points,
+            }
+        })
+        .collect();
+    Ok(GradeReport {
+        project: report.project,
+        grades,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::grade::{check_policy, grade_project, Policy, Violation};
+    use crate::testing::TempProject;
+    use crate::Project;
+
+    const POLICY: &str = r#"{
+        "allowed": ["Nand"],
+        "order": ["Not", "And", "Or", "Xor"],
+        "chips": { "Mux": ["Not", "And", "Or"] },
+        "points": { "Xor": 2 },
+        "penalty": 0.5
+    }"#;
+
+    #[test]
+    fn checks_allowed_parts() {
+        let policy = Policy::parse(POLICY).unwrap();
+        let allowed = |chip| {
+            policy
+                .allowed(chip)
+                .map(|parts| parts.into_iter().collect::<Vec<_>>())
+        };
+        assert_eq!(allowed("Not"), Some(vec!["Nand"]));
+        assert_eq!(allowed("Or"), Some(vec!["And", "Nand", "Not"]));
+        assert_eq!(allowed("Mux"), Some(vec!["And", "Not", "Or"]));
+        assert_eq!(allowed("Mux16"), Some(vec!["Nand"]));
+        assert_eq!(Policy::default().allowed("Mux16"), None);
+        assert!(Policy::parse("{\"allowed\": 3}").is_err());
+
+        let mut project = Project::new();
+        let _ = project
+            .add_hdl("CHIP And { IN a, b; OUT out; PARTS: And16(a[0]=a, b[0]=b, out[0]=out); }")
+            .unwrap();
+        let xor = project
+            .add_hdl(
+                "CHIP Xor { IN a, b; OUT out; PARTS:
+                 Or(a=a, b=b, out=or); Nand(a=a, b=b, out=nand); And(a=or, b=nand, out=out); }",
+            )
+            .unwrap()
+            .clone();
+        // Xor itself is fine, but the And it is built from uses a builtin it may not
+        assert_eq!(
+            check_policy(&xor, &project, &policy),
+            vec![Violation {
+                chip: "And".to_string(),
+                part: "And16".to_string()
+            }]
+        );
+        assert_eq!(
+            check_policy(
+                &xor,
+                &project,
+                &Policy::parse("{\"allowed\": [\"Nand\"]}").unwrap()
+            )
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>(),
+            vec![
+                "And may not use And16",
+                "Xor may not use And",
+                "Xor may not use Or"
+            ]
+        );
+    }
+
+    #[test]
+    fn grades_projects() {
+        let chips = [
+            (
+                "And",
+                "CHIP And { IN a, b; OUT out; PARTS: Nand(a=a, b=b, out=n); Not(in=n, out=out); }",
+            ),
+            // wrong
+            (
+                "Mux",
+                "CHIP Mux { IN a, b, sel; OUT out; PARTS: Or(a=a, b=b, out=out); }",
+            ),
+            ("Not", "CHIP Not { IN in; OUT out; PARTS: Nand(a=in, b=in, out=out); }"),
+            (
+                "Or",
+                "CHIP Or { IN a, b; OUT out; PARTS:
+                 Not(in=a, out=na); Not(in=b, out=nb); Nand(a=na, b=nb, out=out); }",
+            ),
+            // correct, but uses a builtin And16
+            (
+                "Xor",
+                "CHIP Xor { IN a, b; OUT out; PARTS:
+                 Or(a=a, b=b, out=or); Nand(a=a, b=b, out=nand); And16(a[0]=or, b[0]=nand, out[0]=out); }",
+            ),
+        ];
+        let project = TempProject::new("grade", &["And", "Mux", "Not", "Or", "Xor"], &chips);
+
+        let report = grade_project(project.path(), &Policy::parse(POLICY).unwrap()).unwrap();
+        let scores: Vec<(&str, f64)> = report
+            .grades
+            .iter()
+            .map(|grade| (grade.chip.as_str(), grade.score))
+            .collect();
+        assert_eq!(
+            scores,
+            vec![
+                ("And", 1.0),
+                ("Mux", 0.0),
+                ("Not", 1.0),
+                ("Or", 1.0),
+                ("Xor", 1.0)
+            ]
+        );
+        assert_eq!((report.score(), report.points()), (4.0, 6.0));
+        let text = report.to_string();
+        assert!(text.contains("Xor: 1/2 passed\n  Xor may not use And16\n"));
+        assert!(text.contains("Mux: 0/1 failed: comparison failure at line "));
+        assert!(text.ends_with("total: 4/6\n"));
+        assert!(report.to_json().contains("\"part\": \"And16\""));
+    }
+}
@@ -0,0 +1,195 @@
+//! Scripted keyboard input
+//!
+//! Hack programs poll the keyboard register at address 24576, which holds the code of the key being pressed or
+//! zero.  A [KeyScript] schedules presses and releases at given clock cycles so interactive programs can be run
+//! headlessly and deterministically, either built in code or parsed from text such as
+//!
+//! ```text
+//! // move the paddle left for 200 cycles
+//! 1000 press left
+//! 1200 release
+//! 1500 press A
+//! ```
+//!
+//! Test scripts change the register directly with `key <key>` and `key release` commands.
+
+use crate::simulator::SimulationError;
+use dict_derive::{FromPyObject, IntoPyObject};
+use serde::{Deserialize, Serialize};
+
+/// Names of the special keys, starting from code 128
+const SPECIAL_KEYS: &[&str] = &[
+    "newline",
+    "backspace",
+    "left",
+    "up",
+    "right",
+    "down",
+    "home",
+    "end",
+    "pageup",
+    "pagedown",
+    "insert",
+    "delete",
+    "esc",
+    "f1",
+    "f2",
+    "f3",
+    "f4",
+    "f5",
+    "f6",
+    "f7",
+    "f8",
+    "f9",
+    "f10",
+    "f11",
+    "f12",
+];
+
+/// The Hack character code of a key
+///
+/// Keys are a single printable character, the name of a special key such as `newline`, `left` or `f1` in any
+/// case, or a code of at least two decimal digits.
+pub fn key_code(key: &str) -> Option<u16> {
+    let mut chars = key.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if (' '..='~').contains(&c) => return Some(c as u16),
+        (Some(c), Some(_)) if c.is_ascii_digit() => return key.parse().ok(),
+        _ => {}
+    }
+    SPECIAL_KEYS
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(key))
+        .map(|index| 128 + index as u16)
+}
+
+/// A change of the keyboard register at the start of a clock cycle
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, FromPyObject, IntoPyObject,
+)]
+pub struct KeyEvent {
+    /// Holds the cycle before which the key changes
+    pub cycle: usize,
+    /// Holds the code of the key now pressed, or zero once keys are released
+    pub key: u16,
+}
+
+/// A type that represents key presses and releases scheduled over simulated time
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct KeyScript {
+    /// events ordered by cycle, later events at the same cycle winning
+    events: Vec<KeyEvent>,
+}
+
+impl KeyScript {
+    /// Create a script without any events
+    pub fn new() -> KeyScript {
+        KeyScript::default()
+    }
+
+    /// Parse a script with one `<cycle> press <key>` or `<cycle> release` event per line
+    ///
+    /// Keys are written as for [key_code].  Blank lines and `//` comments are ignored.
+    pub fn parse(text: &str) -> Result<KeyScript, SimulationError> {
+        let mut script = KeyScript::new();
+        for (number, line) in text.lines().enumerate() {
+            let line = line.split("//").next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let invalid = |details: &str| {
+                SimulationError::InvalidScript(format!("line {}: {}", number + 1, details))
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let cycle = words[0]
+                .parse()
+                .map_err(|_| invalid(&format!("expected a cycle, found {}", words[0])))?;
+            match words[1..] {
+                ["press", key] => {
+                    let code =
+                        key_code(key).ok_or_else(|| invalid(&format!("unknown key {}", key)))?;
+                    script.press(cycle, code)
+                }
+                ["release"] => script.release(cycle),
+                _ => return Err(invalid("expected press <key> or release")),
+            }
+        }
+        Ok(script)
+    }
+
+    /// Press a key, given by its code, before the given cycle
+    pub fn press(&mut self, cycle: usize, key: u16) {
+        let index = self.events.partition_point(|event| event.cycle <= cycle);
+        self.events.insert(index, KeyEvent { cycle, key });
+    }
+
+    /// Release every key before the given cycle
+    pub fn release(&mut self, cycle: usize) {
+        self.press(cycle, 0)
+    }
+
+    /// The scheduled events in order
+    pub fn events(&self) -> &[KeyEvent] {
+        &self.events
+    }
+
+    /// The key that changes before the given cycle, if any
+    pub fn change_at(&self, cycle: usize) -> Option<u16> {
+        let end = self.events.partition_point(|event| event.cycle <= cycle);
+        self.events[..end]
+            .last()
+            .filter(|event| event.cycle == cycle)
+            .map(|event| event.key)
+    }
+
+    /// The key held down during the given cycle, or zero
+    pub fn key_at(&self, cycle: usize) -> u16 {
+        let end = self.events.partition_point(|event| event.cycle <= cycle);
+        self.events[..end].last().map_or(0, |event| event.key)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keyboard::{key_code, KeyScript};
+    use crate::simulator::SimulationError;
+
+    #[test]
+    fn names_keys() {
+        assert_eq!(key_code("A"), Some(65));
+        assert_eq!(key_code(" "), Some(32));
+        assert_eq!(key_code("newline"), Some(128));
+        assert_eq!(key_code("Left"), Some(130));
+        assert_eq!(key_code("F12"), Some(152));
+        assert_eq!(key_code("140"), Some(140));
+        assert_eq!(key_code("tab"), None);
+    }
+
+    #[test]
+    fn schedules_events() {
+        let script = KeyScript::parse(
+            "// press A for a while
+            1000 press A
+            1200 release
+
+            10 press right // first",
+        )
+        .unwrap();
+        assert_eq!(script.events().len(), 3);
+        assert_eq!(script.key_at(0), 0);
+        assert_eq!(script.key_at(500), 132);
+        assert_eq!(script.key_at(1000), 65);
+        assert_eq!(script.key_at(1199), 65);
+        assert_eq!(script.key_at(5000), 0);
+        assert_eq!(script.change_at(1200), Some(0));
+        assert_eq!(script.change_at(1201), None);
+        assert!(matches!(
+            KeyScript::parse("5 press nothing"),
+            Err(SimulationError::InvalidScript(_))
+        ));
+        assert!(matches!(
+            KeyScript::parse("later release"),
+            Err(SimulationError::InvalidScript(_))
+        ));
+    }
+}
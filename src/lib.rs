@@ -13,7 +13,36 @@ unused_results,
 warnings
 )]
 
+pub mod bitsliced;
+mod builtins;
+pub mod compare;
+pub mod computer;
+pub mod coverage;
+pub mod differential;
+pub mod equivalence;
+pub mod fault;
+pub mod flatten;
+pub mod format;
+pub mod generate;
+pub mod grade;
+pub mod keyboard;
+pub mod limits;
+pub mod memory;
+pub mod metrics;
+pub mod mutation;
+pub mod probe;
+pub mod project;
 mod python;
+mod random;
+pub mod runner;
+pub mod screen;
+pub mod simulator;
+pub mod suite;
+pub mod test_script;
+#[cfg(test)]
+mod testing;
+pub mod truth_table;
+pub mod waveform;
 
 use core::fmt;
 use nom::branch::alt;
@@ -21,14 +50,28 @@ use nom::bytes::complete::{tag, take_till, take_until, take_while1};
 use nom::character::complete::{line_ending, multispace1};
 use nom::character::streaming::digit1;
 use nom::character::{is_alphabetic, is_alphanumeric};
-use nom::combinator::{not, opt};
+use nom::combinator::{not, opt, peek};
 use nom::error::{convert_error, VerboseError, context};
 use nom::multi::{many0, many1};
-use nom::{Err, IResult};
+use nom::sequence::preceded;
+use nom::{Err, IResult, Offset};
 use std::error::Error;
 use serde::{Deserialize, Serialize};
 use dict_derive::{FromPyObject, IntoPyObject};
 
+pub use bitsliced::Program;
+pub use computer::Computer;
+pub use equivalence::{check_equivalence, Equivalence};
+pub use flatten::{flatten, Cell, FlattenError, Net, Netlist, Primitive};
+pub use keyboard::KeyScript;
+pub use memory::Memory;
+pub use metrics::{metrics, Metrics};
+pub use project::Project;
+pub use simulator::{SimulationError, Simulator, Time};
+pub use test_script::Span;
+pub use truth_table::TruthTable;
+pub use waveform::Waveform;
+
 /// A type that represents a pin
 ///
 /**
@@ -67,6 +110,30 @@ impl fmt::Debug for Pin {
     }
 }
 
+impl Pin {
+    /// Width of the pin when it appears in an `IN`/`OUT` declaration
+    ///
+    /// `a` is one bit wide and `a[16]` is sixteen bits wide
+    pub fn declared_width(&self) -> usize {
+        if self.start < 0 {
+            1
+        } else {
+            self.start as usize
+        }
+    }
+
+    /// Bit range selected by the pin when it appears in a part connection
+    ///
+    /// `a[0..3]` selects `Some((0, 3))`, `a[2]` selects `Some((2, 2))` and `a` selects the whole bus (`None`)
+    pub fn range(&self) -> Option<(usize, usize)> {
+        if self.start < 0 || self.end < 0 {
+            None
+        } else {
+            Some((self.start as usize, self.end as usize))
+        }
+    }
+}
+
 /// A type that represents a chip
 ///
 /**
@@ -118,8 +185,34 @@ pub struct Part {
 
 
 
+/// Where each piece of a [Chip] was written in its HDL source
+///
+/// The lists run parallel to those of the chip: `inputs[0]` is the span of `chip.inputs[0]`, and so on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct ChipSpans {
+    /// Holds the span of the chip's name
+    pub name: Span,
+    /// Holds the span of each input pin declaration
+    pub inputs: Vec<Span>,
+    /// Holds the span of each output pin declaration
+    pub outputs: Vec<Span>,
+    /// Holds the spans of each part
+    pub parts: Vec<PartSpans>,
+}
+
+/// Where a [Part] and its connections were written in its HDL source
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct PartSpans {
+    /// Holds the span of the whole part, from its name to the closing `);`
+    pub span: Span,
+    /// Holds the span of each internal pin, the first of a `{}={}` pair
+    pub internal: Vec<Span>,
+    /// Holds the span of each external pin, the second of a `{}={}` pair
+    pub external: Vec<Span>,
+}
+
 /// Error returned when HDL cannot be parsed
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct HDLParseError {
     details: String,
 }
@@ -154,7 +247,7 @@ fn separator(text: &str) -> IResult<&str, (), VerboseError<&str>> {
         Ok((text, ()))
     }
     fn comment_multiline(text: &str) -> IResult<&str, (), VerboseError<&str>> {
-        let (text, _) = tag("/**")(text)?;
+        let (text, _) = tag("/*")(text)?;
         let (text, _) = take_until("*/")(text)?;
         let (text, _) = tag("*/")(text)?;
 
@@ -212,9 +305,8 @@ fn pin(text: &str) -> IResult<&str, Pin, VerboseError<&str>> {
     }
 
     let (text, _) = take_till(|x| is_alphabetic(x as u8))(text)?;
-    let (text, name) = take_till(|x| match x {
-        ',' | ')' | ';' | '=' | '[' | ' ' => true,
-        _ => false,
+    let (text, name) = take_till(|x: char| {
+        matches!(x, ',' | ')' | ';' | '=' | '[') || x.is_whitespace()
     })(text)?;
     let (text, _) = separator(text)?;
     match pin_index(text) {
@@ -239,50 +331,83 @@ fn pin(text: &str) -> IResult<&str, Pin, VerboseError<&str>> {
     }
 }
 
+/// A pin along with the source text it was parsed from
+type SpannedPin<'a> = (Pin, &'a str);
+
+/// Parses a pin like [pin], along with the source text it was parsed from
+fn spanned_pin(text: &str) -> IResult<&str, SpannedPin<'_>, VerboseError<&str>> {
+    let (text, _) = take_till(|x| is_alphabetic(x as u8))(text)?;
+    let (rest, pin) = pin(text)?;
+    Ok((rest, (pin, text[..text.len() - rest.len()].trim_end())))
+}
+
+/// Source text a [Chip] was parsed from
+struct ChipSource<'a> {
+    name: &'a str,
+    inputs: Vec<&'a str>,
+    outputs: Vec<&'a str>,
+    parts: Vec<PartSource<'a>>,
+}
+
+/// Source text a [Part] was parsed from
+struct PartSource<'a> {
+    text: &'a str,
+    internal: Vec<&'a str>,
+    external: Vec<&'a str>,
+}
+
 /// Parses a part descriptor into a [Part]
 ///
 /// `Test(a[0..3]=a[0..3],b=b,out=out);` will become a part with the name `Test` and the pins parsed with [part_pin]
-fn part(text: &str) -> IResult<&str, Part, VerboseError<&str>> {
-    fn internal_part(text: &str) -> IResult<&str, (Pin, Pin), VerboseError<&str>> {
+fn part(text: &str) -> IResult<&str, (Part, PartSource<'_>), VerboseError<&str>> {
+    fn internal_part(text: &str) -> IResult<&str, (SpannedPin<'_>, SpannedPin<'_>), VerboseError<&str>> {
         let (text, _) = not(tag(")"))(text)?;
-        let (text, pin1) = pin(text)?;
+        let (text, pin1) = spanned_pin(text)?;
         let (text, _) = tag("=")(text)?;
-        let (text, pin2) = pin(text)?;
+        let (text, pin2) = spanned_pin(text)?;
         Ok((text, (pin1, pin2)))
     }
 
     let (text, _) = separator(text)?;
+    let start = text;
     let (text, name) = context("expected nonzero length alphanumeric identifier", take_while1(|x| is_alphanumeric(x as u8)))(text)?;
     let (text, _) = separator(text)?;
     let (text, _) = context("symbol \"(\"", tag("("))(text)?;
     let (text, pins) = many0(internal_part)(text)?;
-    let pins = pins
-        .iter()
-        .map(|&(ref a, ref b)| (a.clone(), b.clone()))
-        .unzip();
+    let (internal, external): (Vec<_>, Vec<_>) = pins.into_iter().unzip();
+    let (internal, internal_text): (Vec<Pin>, Vec<&str>) = internal.into_iter().unzip();
+    let (external, external_text): (Vec<Pin>, Vec<&str>) = external.into_iter().unzip();
 
     let (text, _) = context("symbol \");\"", tag(");"))(text)?;
+    let source = PartSource {
+        text: &start[..start.len() - text.len()],
+        internal: internal_text,
+        external: external_text,
+    };
     let (text, _) = separator(text)?;
     Ok((
         text,
-        Part {
-            name: name.to_string(),
-            internal: pins.0,
-            external: pins.1,
-        },
+        (
+            Part {
+                name: name.to_string(),
+                internal,
+                external,
+            },
+            source,
+        ),
     ))
 }
 
 /// parse input/output pin line with arbitrary label
 ///
-/// `IN a, b;` would parse into a `Vec<Pin>` with two pins - a and b
+/// `IN a, b;` would parse into a `Vec` with two pins - a and b - each with the source text it was parsed from
 fn parse_io_pins<'a>(
     text: &'a str,
     label: &'static str,
-) -> IResult<&'a str, Vec<Pin>, VerboseError<&'a str>> {
-    fn interface_pin(text: &str) -> IResult<&str, Pin, VerboseError<&str>> {
+) -> IResult<&'a str, Vec<SpannedPin<'a>>, VerboseError<&'a str>> {
+    fn interface_pin(text: &str) -> IResult<&str, SpannedPin<'_>, VerboseError<&str>> {
         let (text, _) = not(tag(";"))(text)?;
-        let (text, pin) = pin(text)?;
+        let (text, pin) = spanned_pin(text)?;
 
         let (text, _) = opt(tag(","))(text)?;
         let (text, _) = separator(text)?;
@@ -304,7 +429,12 @@ fn parse_io_pins<'a>(
 
 /// parse_hdl will consume text and return `Result<Chip, Error>` depending on if it can successfully be parsed
 pub fn parse_hdl(text: &str) -> Result<Chip, HDLParseError> {
-    fn parse_hdl_internal(text: &str) -> IResult<&str, Chip, VerboseError<&str>> {
+    parse_hdl_spans(text).map(|(chip, _)| chip)
+}
+
+/// Parse HDL like [parse_hdl], also returning where in `text` each part of the chip was written
+pub fn parse_hdl_spans(text: &str) -> Result<(Chip, ChipSpans), HDLParseError> {
+    fn parse_hdl_internal(text: &str) -> IResult<&str, (Chip, ChipSource<'_>), VerboseError<&str>> {
         let (text, _) = separator(text)?;
         let (text, _) = context("symbol \"CHIP\"", tag("CHIP"))(text)?;
 
@@ -312,26 +442,62 @@ pub fn parse_hdl(text: &str) -> Result<Chip, HDLParseError> {
         let (text, chip_name) = context("alphanumeric identifier (for name)", take_till(|x| !is_alphanumeric(x as u8)))(text)?;
 
         let (text, inputs) = parse_io_pins(text, "IN")?;
-        let (text, outputs) = parse_io_pins(text, "OUT")?;
+        // chips without outputs, such as Computer, leave out the OUT statement entirely
+        let (text, _) = opt(tag(";"))(text)?;
+        let (text, has_outputs) = opt(peek(preceded(separator, tag("OUT"))))(text)?;
+        let (text, outputs) = if has_outputs.is_some() {
+            parse_io_pins(text, "OUT")?
+        } else {
+            (text, vec![])
+        };
 
         let (text, _) = take_until("PARTS:")(text)?;
         let (text, _) = context("symbol \"PARTS:\"", tag("PARTS:"))(text)?;
         let (text, _) = separator(text)?;
         let (text, parts) = many0(part)(text)?;
+        let (parts, sources) = parts.into_iter().unzip();
 
         Ok((
             text,
-            Chip {
-                name: chip_name.to_string(),
-                inputs,
-                outputs,
-                parts,
-            },
+            (
+                Chip {
+                    name: chip_name.to_string(),
+                    inputs: inputs.iter().map(|(pin, _)| pin.clone()).collect(),
+                    outputs: outputs.iter().map(|(pin, _)| pin.clone()).collect(),
+                    parts,
+                },
+                ChipSource {
+                    name: chip_name,
+                    inputs: inputs.into_iter().map(|(_, source)| source).collect(),
+                    outputs: outputs.into_iter().map(|(_, source)| source).collect(),
+                    parts: sources,
+                },
+            ),
         ))
     }
 
+    let span = |source: &str| Span {
+        start: text.offset(source),
+        end: text.offset(source) + source.len(),
+    };
     match parse_hdl_internal(text) {
-        Ok((_, chip)) => Ok(chip),
+        Ok((_, (chip, source))) => Ok((
+            chip,
+            ChipSpans {
+                name: span(source.name),
+                inputs: source.inputs.into_iter().map(span).collect(),
+                outputs: source.outputs.into_iter().map(span).collect(),
+                parts: source
+                    .parts
+                    .into_iter()
+                    .map(|part| PartSpans {
+                        span: span(part.text),
+                        internal: part.internal.into_iter().map(span).collect(),
+                        external: part.external.into_iter().map(span).collect(),
+                    })
+                    .collect(),
+            },
+        )),
         Err(Err::Error(e)) | Err(Err::Failure(e)) => {
             Err(HDLParseError::new(&convert_error(text, e)))
         }
@@ -343,7 +509,7 @@ pub fn parse_hdl(text: &str) -> Result<Chip, HDLParseError> {
 
 #[cfg(test)]
 mod tests {
-    use crate::{parse_hdl, parse_io_pins, Pin};
+    use crate::{parse_hdl, parse_hdl_spans, parse_io_pins, Pin};
     use std::io::Error;
 
     #[test]
@@ -509,7 +675,7 @@ aaaa
 ";
         let (_, pins) = parse_io_pins(text, "IN").unwrap_or(("", vec![]));
         assert_eq!(
-            pins,
+            pins.into_iter().map(|(pin, _)| pin).collect::<Vec<_>>(),
             vec![
                 Pin {
                     name: "a".to_string(),
@@ -526,6 +692,72 @@ aaaa
         Ok(())
     }
 
+    #[test]
+    fn parses_chip_without_outputs() -> Result<(), Error> {
+        let chip = parse_hdl(
+            "CHIP Computer {
+    IN reset;
+
+    PARTS:
+    ROM32K(address=pc, out=instruction);
+}",
+        )
+        .unwrap();
+        assert!(chip.outputs.is_empty());
+        assert_eq!(chip.parts[0].external[1].name, "instruction");
+
+        let chip = parse_hdl(
+            "CHIP Computer {
+    IN reset;
+    // no OUT statement, the screen shows the results
+    PARTS:
+    ROM32K(address=pc, out=instruction);
+}",
+        )
+        .unwrap();
+        assert!(chip.outputs.is_empty());
+        assert_eq!(chip.parts.len(), 1);
+
+        let chip = parse_hdl(
+            "CHIP Not {
+    IN in; // OUT is the inverse of in
+    OUT out;
+    PARTS:
+    Nand(a=in, b=in, out=out);
+}",
+        )
+        .unwrap();
+        assert_eq!(chip.outputs[0].name, "out");
+
+        let chip = parse_hdl("CHIP Not { IN in; /* a note */ OUT out; PARTS: Nand(a=in, b=in, out=out); }")
+            .unwrap();
+        assert_eq!(chip.outputs[0].name, "out");
+        Ok(())
+    }
+
+    #[test]
+    fn parses_spans() -> Result<(), Error> {
+        let hdl = "// a comment
+CHIP And {
+    IN a, b[16];
+    OUT out;
+    PARTS:
+    Nand(a=a, b = b[0..3], out=nand);
+    Not(in=nand, out=out);
+}";
+        let (chip, spans) = parse_hdl_spans(hdl).unwrap();
+        assert_eq!(chip, parse_hdl(hdl).unwrap());
+        let text = |span: crate::Span| &hdl[span.start..span.end];
+        assert_eq!(text(spans.name), "And");
+        assert_eq!(text(spans.inputs[1]), "b[16]");
+        assert_eq!(text(spans.outputs[0]), "out");
+        assert_eq!(text(spans.parts[0].span), "Nand(a=a, b = b[0..3], out=nand);");
+        assert_eq!(text(spans.parts[0].external[1]), "b[0..3]");
+        assert_eq!(text(spans.parts[1].internal[0]), "in");
+        assert_eq!(spans.parts[1].span.line_column(hdl), (7, 5));
+        Ok(())
+    }
+
     #[test]
     fn test_pin_debug_display() -> Result<(), Error> {
         let index_same_formatted: String = format!(
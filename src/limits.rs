@@ -0,0 +1,137 @@
+//! Bounds on the resources a simulation may use
+//!
+//! Student chips and test scripts can take arbitrarily long: a `while` loop whose condition never changes runs
+//! forever, and a hierarchy of 16 bit chips built from 16 bit chips grows to millions of gates.  [Limits] stop
+//! such runs with [SimulationError::LimitExceeded], so a batch of tests always finishes.  Every limit is off
+//! unless given.
+//!
+//! ```
+//! use nand2tetris_hdl_parser::limits::{Limit, Limits};
+//! use nand2tetris_hdl_parser::runner::run_script_with_limits;
+//! use nand2tetris_hdl_parser::test_script::parse_tst;
+//! use nand2tetris_hdl_parser::{Project, SimulationError};
+//!
+//! let project = Project::new();
+//! let script = parse_tst("set in 1, set load 1; while out = out { tick, tock; }").unwrap();
+//! let limits = Limits {
+//!     cycles: Some(100),
+//!     ..Limits::default()
+//! };
+//! let run = run_script_with_limits(&script, project.chip("Bit").unwrap(), &project, None, &limits);
+//! assert!(matches!(run, Err(SimulationError::LimitExceeded(Limit::Cycles(100)))));
+//! ```
+
+use crate::simulator::SimulationError;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::time::Instant;
+
+/// The most a simulation may use of each resource, `None` meaning unlimited
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// Holds the most clock cycles a test script may simulate
+    pub cycles: Option<usize>,
+    /// Holds the most primitive gates, including flip-flops and builtin memories, a flattened chip may contain
+    pub gates: Option<usize>,
+    /// Holds the most levels of parts beneath the simulated chip, which itself is level 0
+    pub depth: Option<usize>,
+    /// Holds the most wall-clock time in milliseconds a test script may run for
+    pub milliseconds: Option<u64>,
+}
+
+/// A limit that was exceeded, with its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Limit {
+    /// More clock cycles were simulated than allowed
+    Cycles(usize),
+    /// The flattened chip has more gates than allowed
+    Gates(usize),
+    /// The chip hierarchy is nested deeper than allowed
+    Depth(usize),
+    /// The script ran longer than the given number of milliseconds
+    Milliseconds(u64),
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Cycles(cycles) => write!(f, "more than {} clock cycles", cycles),
+            Limit::Gates(gates) => write!(f, "more than {} gates", gates),
+            Limit::Depth(depth) => write!(f, "parts nested more than {} levels deep", depth),
+            Limit::Milliseconds(milliseconds) => {
+                write!(f, "running for more than {} ms", milliseconds)
+            }
+        }
+    }
+}
+
+impl Limits {
+    /// Fail if more clock cycles than allowed have completed
+    pub(crate) fn check_cycles(&self, cycles: usize) -> Result<(), SimulationError> {
+        match self.cycles {
+            Some(limit) if cycles > limit => {
+                Err(SimulationError::LimitExceeded(Limit::Cycles(limit)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fail if more time than allowed has passed since `started`
+    pub(crate) fn check_time(&self, started: Instant) -> Result<(), SimulationError> {
+        match self.milliseconds {
+            Some(limit) if started.elapsed().as_millis() > limit as u128 => {
+                Err(SimulationError::LimitExceeded(Limit::Milliseconds(limit)))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flatten::flatten_with_limits;
+    use crate::limits::{Limit, Limits};
+    use crate::runner::run_script_with_limits;
+    use crate::simulator::SimulationError;
+    use crate::test_script::parse_tst;
+    use crate::Project;
+
+    #[test]
+    fn bounds_hierarchies() {
+        let project = Project::new();
+        let and = project.chip("And").unwrap();
+        let limits = |gates, depth| Limits {
+            gates,
+            depth,
+            ..Limits::default()
+        };
+        // And uses a Nand and a Not, which uses another Nand
+        assert!(flatten_with_limits(and, &project, &limits(None, Some(2))).is_ok());
+        assert!(matches!(
+            flatten_with_limits(and, &project, &limits(None, Some(1))),
+            Err(SimulationError::LimitExceeded(Limit::Depth(1)))
+        ));
+        assert!(flatten_with_limits(and, &project, &limits(Some(2), None)).is_ok());
+        assert!(matches!(
+            flatten_with_limits(and, &project, &limits(Some(1), None)),
+            Err(SimulationError::LimitExceeded(Limit::Gates(1)))
+        ));
+    }
+
+    #[test]
+    fn stops_endless_scripts() {
+        let project = Project::new();
+        let and = project.chip("And").unwrap();
+        let script = parse_tst("while out = 0 { eval; }").unwrap();
+        let limits = Limits {
+            milliseconds: Some(10),
+            ..Limits::default()
+        };
+        let error = run_script_with_limits(&script, and, &project, None, &limits).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "limit exceeded: running for more than 10 ms"
+        );
+    }
+}
@@ -0,0 +1,186 @@
+//! Native implementations of the memory and I/O builtins
+//!
+//! `RAM16K`, `Screen` and `ROM32K` are stored sparsely in pages that are only allocated once written, so a
+//! simulated computer only pays for the memory its program touches.  The keyboard is a single register.
+
+use crate::flatten::{Cell, Net, Primitive};
+
+/// Number of words in a page of sparse storage
+const PAGE: usize = 256;
+
+/// A type that represents word addressed memory contents
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Memory {
+    size: usize,
+    pages: Vec<Option<Box<[u16; PAGE]>>>,
+}
+
+impl Memory {
+    /// Create zeroed memory holding `size` words
+    pub fn new(size: usize) -> Memory {
+        Memory {
+            size,
+            pages: vec![None; size.div_ceil(PAGE)],
+        }
+    }
+
+    /// Number of words the memory holds
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    /// Whether the memory holds no words at all
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// Read a word; addresses beyond the end of the memory read as zero
+    pub fn peek(&self, address: usize) -> u16 {
+        if address >= self.size {
+            return 0;
+        }
+        self.pages[address / PAGE]
+            .as_ref()
+            .map_or(0, |page| page[address % PAGE])
+    }
+
+    /// Write a word; writes beyond the end of the memory are ignored
+    pub fn poke(&mut self, address: usize, value: u16) {
+        if address >= self.size {
+            return;
+        }
+        match &mut self.pages[address / PAGE] {
+            Some(page) => page[address % PAGE] = value,
+            None if value == 0 => {}
+            page => {
+                let mut words = Box::new([0; PAGE]);
+                words[address % PAGE] = value;
+                *page = Some(words);
+            }
+        }
+    }
+
+    /// Write consecutive words starting at address zero
+    pub fn load(&mut self, words: &[u16]) {
+        for (address, &word) in words.iter().enumerate() {
+            self.poke(address, word);
+        }
+    }
+
+    /// Reset every word to zero
+    pub fn clear(&mut self) {
+        for page in self.pages.iter_mut() {
+            *page = None;
+        }
+    }
+}
+
+/// Number of words a memory primitive holds
+pub(crate) fn size(primitive: Primitive) -> usize {
+    match primitive {
+        Primitive::RAM16K => 1 << 14,
+        Primitive::ROM32K => 1 << 15,
+        Primitive::Screen => 1 << 13,
+        Primitive::Keyboard => 1,
+        Primitive::Nand | Primitive::DFF => 0,
+    }
+}
+
+/// A memory primitive inside a simulated netlist and its contents
+#[derive(Debug, Clone)]
+pub(crate) struct MemoryCell {
+    pub(crate) path: String,
+    pub(crate) primitive: Primitive,
+    pub(crate) contents: Memory,
+    input: Vec<Net>,
+    load: Option<Net>,
+    address: Vec<Net>,
+    output: Vec<Net>,
+    /// address and value to write on the next tock
+    pending: Option<(usize, u16)>,
+}
+
+impl MemoryCell {
+    pub(crate) fn new(cell: &Cell) -> MemoryCell {
+        let pin = |name: &str| {
+            cell.primitive
+                .inputs()
+                .iter()
+                .position(|&(pin, _)| pin == name)
+                .map(|index| cell.inputs[index].clone())
+        };
+        MemoryCell {
+            path: cell.path.clone(),
+            primitive: cell.primitive,
+            contents: Memory::new(size(cell.primitive)),
+            input: pin("in").unwrap_or_default(),
+            load: pin("load").map(|load| load[0]),
+            address: pin("address").unwrap_or_default(),
+            output: cell.outputs[0].clone(),
+            pending: None,
+        }
+    }
+
+    fn address(&self, values: &[bool]) -> usize {
+        read(&self.address, values) as usize
+    }
+
+    /// Whether the memory drives a net with its output
+    pub(crate) fn drives(&self, net: Net) -> bool {
+        self.output.contains(&net)
+    }
+
+    /// Drive the output with the word at the current address
+    pub(crate) fn eval(&self, values: &mut [bool]) {
+        let word = self.contents.peek(self.address(values));
+        for (bit, &net) in self.output.iter().enumerate() {
+            values[net] = word >> bit & 1 == 1;
+        }
+    }
+
+    /// Sample the inputs on the rising clock edge
+    pub(crate) fn tick(&mut self, values: &[bool]) {
+        self.pending = match self.load {
+            Some(load) if values[load] => {
+                Some((self.address(values), read(&self.input, values) as u16))
+            }
+            _ => None,
+        };
+    }
+
+    /// Commit the sampled write on the falling clock edge
+    pub(crate) fn tock(&mut self) {
+        if let Some((address, value)) = self.pending.take() {
+            self.contents.poke(address, value);
+        }
+    }
+}
+
+/// Read a group of nets as an unsigned number, least significant bit first
+pub(crate) fn read(nets: &[Net], values: &[bool]) -> u64 {
+    nets.iter()
+        .take(64)
+        .enumerate()
+        .fold(0, |value, (bit, &net)| value | (values[net] as u64) << bit)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::memory::Memory;
+
+    #[test]
+    fn stores_sparsely() {
+        let mut memory = Memory::new(1 << 14);
+        assert_eq!(memory.peek(1000), 0);
+        memory.poke(1000, 0xbeef);
+        memory.poke(5, 0);
+        assert_eq!(memory.peek(1000), 0xbeef);
+        assert_eq!(memory.pages.iter().filter(|page| page.is_some()).count(), 1);
+        memory.poke(1 << 14, 1);
+        assert_eq!(memory.peek(1 << 14), 0);
+        memory.load(&[1, 2, 3]);
+        assert_eq!(memory.peek(2), 3);
+        memory.clear();
+        assert_eq!(memory.peek(1000), 0);
+    }
+}
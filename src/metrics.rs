@@ -0,0 +1,140 @@
+//! Gate counts and critical path depth of chips
+
+use crate::flatten::{flatten, FlattenError, Netlist, Primitive};
+use crate::{Chip, Project};
+use dict_derive::{FromPyObject, IntoPyObject};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Cost of one kind of part used directly by a chip
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, FromPyObject, IntoPyObject)]
+pub struct PartMetrics {
+    /// Holds the name of the part
+    pub name: String,
+    /// Holds how many times the chip uses the part
+    pub instances: usize,
+    /// Holds the number of Nand gates inside all instances of the part
+    pub nand: usize,
+    /// Holds the number of DFFs inside all instances of the part
+    pub dff: usize,
+}
+
+/// Cost of a chip once flattened down to primitives
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, FromPyObject, IntoPyObject)]
+pub struct Metrics {
+    /// Holds the name of the chip
+    pub chip: String,
+    /// Holds the total number of Nand gates
+    pub nand: usize,
+    /// Holds the total number of DFFs
+    pub dff: usize,
+    /// Holds the longest combinational path measured in Nand gates
+    pub depth: usize,
+    /// Holds the cost of each part the chip uses directly, sorted by part name
+    pub parts: Vec<PartMetrics>,
+}
+
+impl Metrics {
+    /// Measure a netlist that has already been flattened
+    pub fn from_netlist(netlist: &Netlist) -> Result<Metrics, FlattenError> {
+        let mut parts: BTreeMap<&str, (Vec<&str>, usize, usize)> = BTreeMap::new();
+        for cell in &netlist.cells {
+            let instance = cell.path[netlist.name.len() + 1..]
+                .split('/')
+                .next()
+                .unwrap_or_default();
+            let name = instance.split('[').next().unwrap_or_default();
+            let entry = parts.entry(name).or_insert((vec![], 0, 0));
+            if entry.0.last() != Some(&instance) {
+                entry.0.push(instance);
+            }
+            match cell.primitive {
+                Primitive::Nand => entry.1 += 1,
+                Primitive::DFF => entry.2 += 1,
+                _ => {}
+            }
+        }
+
+        // sources such as inputs, constants and clocked outputs sit at level zero
+        let mut level = vec![0; netlist.nets];
+        for index in netlist.topological_order()? {
+            let cell = &netlist.cells[index];
+            let deepest = cell
+                .primitive
+                .combinational_inputs()
+                .iter()
+                .flat_map(|&pin| &cell.inputs[pin])
+                .map(|&net| level[net])
+                .max()
+                .unwrap_or(0);
+            let out = match cell.primitive {
+                Primitive::Nand => deepest + 1,
+                _ => deepest,
+            };
+            for &net in cell.outputs.iter().flatten() {
+                level[net] = out;
+            }
+        }
+
+        Ok(Metrics {
+            chip: netlist.name.clone(),
+            nand: netlist.count(Primitive::Nand),
+            dff: netlist.count(Primitive::DFF),
+            depth: level.into_iter().max().unwrap_or(0),
+            parts: parts
+                .into_iter()
+                .map(|(name, (instances, nand, dff))| {
+                    let mut instances = instances;
+                    instances.sort_unstable();
+                    instances.dedup();
+                    PartMetrics {
+                        name: name.to_string(),
+                        instances: instances.len(),
+                        nand,
+                        dff,
+                    }
+                })
+                .collect(),
+        })
+    }
+}
+
+/// Flatten a chip and measure its gate counts and critical path depth
+pub fn metrics(chip: &Chip, project: &Project) -> Result<Metrics, FlattenError> {
+    Metrics::from_netlist(&flatten(chip, project)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metrics::{metrics, PartMetrics};
+    use crate::Project;
+
+    #[test]
+    fn measures_builtin_chips() {
+        let project = Project::new();
+        let xor = metrics(project.chip("Xor").unwrap(), &project).unwrap();
+        assert_eq!((xor.nand, xor.dff, xor.depth), (4, 0, 3));
+
+        let register = metrics(project.chip("Register").unwrap(), &project).unwrap();
+        assert_eq!(register.dff, 16);
+        assert_eq!(
+            register.parts,
+            vec![PartMetrics {
+                name: "Bit".to_string(),
+                instances: 16,
+                nand: 16 * 4,
+                dff: 16,
+            }]
+        );
+    }
+
+    #[test]
+    fn measures_adder_depth() {
+        let project = Project::new();
+        let add16 = metrics(project.chip("Add16").unwrap(), &project).unwrap();
+        let half = metrics(project.chip("HalfAdder").unwrap(), &project).unwrap();
+        let full = metrics(project.chip("FullAdder").unwrap(), &project).unwrap();
+        assert_eq!(add16.nand, half.nand + 15 * full.nand);
+        assert!(add16.depth > full.depth * 8);
+    }
+}
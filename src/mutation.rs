@@ -0,0 +1,472 @@
+//! Mutation testing of chip test scripts
+//!
+//! Small faults are planted in the parts of a chip that passes its test: two input connections swapped, a part
+//! replaced by a sibling with the same pins such as `And` by `Or`, a constant input flipped, or a slice moved by one
+//! bit.  Each mutant is run against the test, which kills it if its output no longer matches the comparison file.
+//! Mutants that survive point at behaviour the test does not check, unless they are equivalent to the chip, like
+//! swapping the inputs of an `And`; survivors of combinational chips are checked for that.
+
+use crate::compare::{compare, Table};
+use crate::equivalence::{check_equivalence, Equivalence};
+use crate::flatten::flatten_with_limits;
+use crate::limits::Limits;
+use crate::project::Resolved;
+use crate::runner::run_simulator;
+use crate::simulator::{SimulationError, Simulator};
+use crate::test_script::TestScript;
+use crate::{Chip, Pin, Project};
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Instant;
+
+/// Groups of builtin chips with the same pins, any of which may replace another
+pub const SIBLINGS: &[&[&str]] = &[
+    &["And", "Or", "Xor", "Nand"],
+    &["And16", "Or16", "Add16"],
+    &["Not16", "Inc16"],
+];
+
+/// A change to one part of a chip
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Mutation {
+    /// Connect each of two input pins of a part to what the other was connected to
+    Swap {
+        /// Holds the position of the part
+        part: usize,
+        /// Holds the position of the first connection within the part
+        first: usize,
+        /// Holds the position of the second connection
+        second: usize,
+    },
+    /// Replace a part by a sibling chip with the same pins
+    Replace {
+        /// Holds the position of the part
+        part: usize,
+        /// Holds the name of the chip that replaces it
+        with: String,
+    },
+    /// Connect an input pin to `false` instead of `true`, or the other way round
+    FlipConstant {
+        /// Holds the position of the part
+        part: usize,
+        /// Holds the position of the connection within the part
+        connection: usize,
+    },
+    /// Move the bits selected on one side of a connection by one position
+    ShiftSlice {
+        /// Holds the position of the part
+        part: usize,
+        /// Holds the position of the connection within the part
+        connection: usize,
+        /// Holds whether the part's own pin is sliced rather than the wire it connects to
+        internal: bool,
+        /// Holds the number of bits the slice moves, `1` or `-1`
+        by: i32,
+    },
+}
+
+/// A chip with one mutation applied
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Mutant {
+    /// Holds the change that was made
+    pub mutation: Mutation,
+    /// Holds the change in words, like `part 2 (And): replaced by Or`
+    pub description: String,
+    /// Holds the mutated chip
+    pub chip: Chip,
+}
+
+/// A pin as written in a part connection, like `a` or `b[0..3]`
+fn written(pin: &Pin) -> String {
+    match pin.range() {
+        None => pin.name.clone(),
+        Some((start, end)) if start == end => format!("{}[{}]", pin.name, start),
+        Some((start, end)) => format!("{}[{}..{}]", pin.name, start, end),
+    }
+}
+
+/// Every mutant of a chip, changing one of its own parts at a time
+///
+/// Only the chip itself is mutated, not the chips its parts are made of.  Some mutants may not flatten, for instance
+/// when a swap connects buses of different widths; [mutation_test] reports those as invalid.
+pub fn mutants(chip: &Chip, project: &Project) -> Vec<Mutant> {
+    let mut mutants = vec![];
+    let mut add = |mutation: Mutation, description: String, apply: &dyn Fn(&mut Chip)| {
+        let mut mutated = chip.clone();
+        apply(&mut mutated);
+        mutants.push(Mutant {
+            mutation,
+            description,
+            chip: mutated,
+        });
+    };
+    for (index, part) in chip.parts.iter().enumerate() {
+        let prefix = format!("part {} ({})", index, part.name);
+        let inputs: Vec<&str> = match project.resolve(&part.name) {
+            Some(Resolved::Chip(definition)) => definition
+                .inputs
+                .iter()
+                .map(|pin| pin.name.as_str())
+                .collect(),
+            Some(Resolved::Primitive(primitive)) => {
+                primitive.inputs().iter().map(|(name, _)| *name).collect()
+            }
+            None => vec![],
+        };
+        let connections: Vec<(usize, &Pin, &Pin)> = part
+            .internal
+            .iter()
+            .zip(&part.external)
+            .enumerate()
+            .map(|(connection, (internal, external))| (connection, internal, external))
+            .collect();
+        let is_input = |internal: &Pin| inputs.contains(&internal.name.as_str());
+
+        for &(first, first_internal, first_external) in &connections {
+            for &(second, second_internal, second_external) in &connections[first + 1..] {
+                if !is_input(first_internal)
+                    || !is_input(second_internal)
+                    || first_external == second_external
+                {
+                    continue;
+                }
+                add(
+                    Mutation::Swap {
+                        part: index,
+                        first,
+                        second,
+                    },
+                    format!(
+                        "{}: {}={} and {}={} swapped",
+                        prefix,
+                        first_internal.name,
+                        written(first_external),
+                        second_internal.name,
+                        written(second_external)
+                    ),
+                    &|chip| {
+                        chip.parts[index].external.swap(first, second);
+                    },
+                );
+            }
+        }
+
+        let siblings = SIBLINGS
+            .iter()
+            .find(|siblings| siblings.contains(&part.name.as_str()))
+            .map_or(&[][..], |siblings| *siblings);
+        for &sibling in siblings {
+            if sibling == part.name || sibling == chip.name || project.resolve(sibling).is_none() {
+                continue;
+            }
+            add(
+                Mutation::Replace {
+                    part: index,
+                    with: sibling.to_string(),
+                },
+                format!("{}: replaced by {}", prefix, sibling),
+                &|chip| chip.parts[index].name = sibling.to_string(),
+            );
+        }
+
+        for &(connection, internal, external) in &connections {
+            if external.name == "true" || external.name == "false" {
+                let flipped = if external.name == "true" {
+                    "false"
+                } else {
+                    "true"
+                };
+                add(
+                    Mutation::FlipConstant {
+                        part: index,
+                        connection,
+                    },
+                    format!(
+                        "{}: {}={} flipped to {}",
+                        prefix, internal.name, external.name, flipped
+                    ),
+                    &|chip| chip.parts[index].external[connection].name = flipped.to_string(),
+                );
+                continue;
+            }
+            for &(is_internal, pin) in &[(true, internal), (false, external)] {
+                if pin.range().is_none() {
+                    continue;
+                }
+                for &by in &[-1, 1] {
+                    if pin.start + by < 0 {
+                        continue;
+                    }
+                    let shifted = Pin {
+                        name: pin.name.clone(),
+                        start: pin.start + by,
+                        end: pin.end + by,
+                    };
+                    add(
+                        Mutation::ShiftSlice {
+                            part: index,
+                            connection,
+                            internal: is_internal,
+                            by,
+                        },
+                        format!(
+                            "{}: {} shifted to {}",
+                            prefix,
+                            written(pin),
+                            written(&shifted)
+                        ),
+                        &|chip| {
+                            let part = &mut chip.parts[index];
+                            let pins = if is_internal {
+                                &mut part.internal
+                            } else {
+                                &mut part.external
+                            };
+                            pins[connection] = shifted.clone();
+                        },
+                    );
+                }
+            }
+        }
+    }
+    mutants
+}
+
+/// What running the test did to a mutant
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The test failed, for the given reason
+    Killed(String),
+    /// The test passed although the mutant behaves differently, or could not be shown to behave the same
+    Survived,
+    /// The test passed and the mutant computes the same function as the chip, so no test can kill it
+    Equivalent,
+    /// The mutant could not be built, for the given reason
+    Invalid(String),
+}
+
+/// The outcome of one mutant
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MutantResult {
+    /// Holds the change that was made
+    pub mutation: Mutation,
+    /// Holds the change in words
+    pub description: String,
+    /// Holds what the test did to the mutant
+    pub outcome: Outcome,
+}
+
+/// The outcomes of every mutant of a chip
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct MutationReport {
+    /// Holds the name of the chip
+    pub chip: String,
+    /// Holds one result per mutant, in the order of [mutants]
+    pub results: Vec<MutantResult>,
+}
+
+impl MutationReport {
+    /// Number of mutants with an outcome like the given one, ignoring the reasons of killed and invalid mutants
+    pub fn count(&self, outcome: &Outcome) -> usize {
+        self.results
+            .iter()
+            .filter(|result| {
+                std::mem::discriminant(&result.outcome) == std::mem::discriminant(outcome)
+            })
+            .count()
+    }
+
+    /// The mutants the test did not kill, excluding equivalent ones
+    pub fn survivors(&self) -> impl Iterator<Item = &MutantResult> {
+        self.results
+            .iter()
+            .filter(|result| result.outcome == Outcome::Survived)
+    }
+
+    /// Share of the mutants that could be killed which were, or `None` if there were none
+    pub fn score(&self) -> Option<f64> {
+        let killed = self.count(&Outcome::Killed(String::new()));
+        let survived = self.count(&Outcome::Survived);
+        if killed + survived == 0 {
+            None
+        } else {
+            Some(killed as f64 / (killed + survived) as f64)
+        }
+    }
+
+    /// The report as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+impl fmt::Display for MutationReport {
+    /// The totals followed by one line per surviving mutant
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} mutants, {} killed, {} survived, {} equivalent, {} invalid",
+            self.chip,
+            self.results.len(),
+            self.count(&Outcome::Killed(String::new())),
+            self.count(&Outcome::Survived),
+            self.count(&Outcome::Equivalent),
+            self.count(&Outcome::Invalid(String::new()))
+        )?;
+        match self.score() {
+            Some(score) => writeln!(f, " ({:.0}% killed)", score * 100.0)?,
+            None => writeln!(f)?,
+        }
+        for survivor in self.survivors() {
+            writeln!(f, "  survived: {}", survivor.description)?;
+        }
+        Ok(())
+    }
+}
+
+/// Run a test script against every mutant of a chip, comparing each output against the expected table
+///
+/// The chip itself must pass the test.  `limits` apply to each run separately, so that a mutant which makes a
+/// `while` loop endless is killed once it goes beyond them rather than hanging.
+pub fn mutation_test(
+    chip: &Chip,
+    project: &Project,
+    script: &TestScript,
+    expected: &Table,
+    directory: Option<&Path>,
+    limits: &Limits,
+) -> Result<MutationReport, SimulationError> {
+    let original = flatten_with_limits(chip, project, limits)?;
+    let run = |simulator: Simulator| -> Result<Option<String>, SimulationError> {
+        let (run, _) = run_simulator(script, simulator, directory, limits, Instant::now())?;
+        let output = Table::parse(&run.output)
+            .map_err(|e| SimulationError::InvalidScript(format!("output: {}", e)))?;
+        Ok(compare(&output, expected).map(|mismatch| mismatch.to_string()))
+    };
+    if let Some(mismatch) = run(Simulator::from_netlist(original.clone())?)? {
+        return Err(SimulationError::InvalidScript(format!(
+            "{} fails its own test: {}",
+            chip.name, mismatch
+        )));
+    }
+
+    let results = mutants(chip, project)
+        .into_iter()
+        .map(|mutant| {
+            let netlist = flatten_with_limits(&mutant.chip, project, limits);
+            let outcome = match netlist
+                .and_then(|netlist| Ok((Simulator::from_netlist(netlist.clone())?, netlist)))
+            {
+                Err(e) => Outcome::Invalid(e.to_string()),
+                Ok((simulator, netlist)) => match run(simulator) {
+                    Err(e) => Outcome::Killed(e.to_string()),
+                    Ok(Some(mismatch)) => Outcome::Killed(mismatch),
+                    Ok(None) => match check_equivalence(&original, &netlist) {
+                        Ok(Equivalence::Equivalent) => Outcome::Equivalent,
+                        _ => Outcome::Survived,
+                    },
+                },
+            };
+            MutantResult {
+                mutation: mutant.mutation,
+                description: mutant.description,
+                outcome,
+            }
+        })
+        .collect();
+    Ok(MutationReport {
+        chip: chip.name.clone(),
+        results,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compare::Table;
+    use crate::generate::{generate_test, Vectors};
+    use crate::limits::Limits;
+    use crate::mutation::{mutants, mutation_test, Mutation, Outcome};
+    use crate::test_script::parse_tst;
+    use crate::Project;
+
+    #[test]
+    fn mutates_parts() {
+        let mut project = Project::new();
+        let chip = project
+            .add_hdl("CHIP Pick { IN a[2]; OUT out; PARTS: And(a=a[0], b=true, out=out); }")
+            .unwrap()
+            .clone();
+        let mutants = mutants(&chip, &project);
+        let descriptions: Vec<&str> = mutants
+            .iter()
+            .map(|mutant| mutant.description.as_str())
+            .collect();
+        assert_eq!(
+            descriptions,
+            vec![
+                "part 0 (And): a=a[0] and b=true swapped",
+                "part 0 (And): replaced by Or",
+                "part 0 (And): replaced by Xor",
+                "part 0 (And): replaced by Nand",
+                "part 0 (And): a[0] shifted to a[1]",
+                "part 0 (And): b=true flipped to false",
+            ]
+        );
+        assert_eq!(
+            mutants[0].mutation,
+            Mutation::Swap {
+                part: 0,
+                first: 0,
+                second: 1
+            }
+        );
+        assert_eq!(mutants[4].chip.parts[0].external[0].start, 1);
+        assert_eq!(mutants[5].chip.parts[0].external[1].name, "false");
+    }
+
+    #[test]
+    fn reports_surviving_mutants() {
+        let mut project = Project::new();
+        let reference = project.chip("Xor").unwrap().clone();
+        let xor = project
+            .add_hdl(
+                "CHIP Xor { IN a, b; OUT out; PARTS:
+                 Or(a=a, b=b, out=or); Nand(a=a, b=b, out=nand); And(a=or, b=nand, out=out); }",
+            )
+            .unwrap()
+            .clone();
+        let test = generate_test(&reference, &project, Vectors::Exhaustive).unwrap();
+        let expected = Table::parse(&test.cmp).unwrap();
+        let script = parse_tst(&test.tst).unwrap();
+        let limits = Limits::default();
+
+        // every input combination kills everything but the equivalent mutants
+        let report = mutation_test(&xor, &project, &script, &expected, None, &limits).unwrap();
+        assert_eq!(report.results.len(), 9);
+        assert_eq!(report.count(&Outcome::Survived), 0);
+        // swapping the inputs of Or, Nand or And changes nothing
+        assert_eq!(report.count(&Outcome::Equivalent), 3);
+        assert_eq!(report.score(), Some(1.0));
+
+        // a test of equal inputs only misses replacing the And by an Or
+        let weak = parse_tst(
+            "output-list a%B3.1.3 b%B3.1.3 out%B3.1.3;
+             set a 0, set b 0, eval, output; set a 1, set b 1, eval, output;",
+        )
+        .unwrap();
+        let lines: Vec<&str> = test.cmp.lines().collect();
+        let expected = Table::parse(&[lines[0], lines[1], lines[4]].join("\n")).unwrap();
+        let report = mutation_test(&xor, &project, &weak, &expected, None, &limits).unwrap();
+        let survivors: Vec<&str> = report
+            .survivors()
+            .map(|survivor| survivor.description.as_str())
+            .collect();
+        assert!(survivors.contains(&"part 0 (Or): replaced by And"));
+        assert!(report.to_string().starts_with("Xor: 9 mutants, "));
+        assert!(report
+            .to_string()
+            .contains("\n  survived: part 0 (Or): replaced by And\n"));
+    }
+}
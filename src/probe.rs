@@ -0,0 +1,174 @@
+//! Watching wires for value changes during simulation
+//!
+//! Any wire can be read by hierarchical path with [Simulator::get](crate::Simulator::get), and the pins of a part
+//! listed with [Simulator::probe](crate::Simulator::probe).  [Simulator::watch](crate::Simulator::watch) registers
+//! a callback that is told about every change of a wire after an evaluation or clock edge.
+
+use crate::flatten::Net;
+use crate::memory;
+use crate::simulator::Time;
+use std::fmt;
+
+/// A change in value of a watched wire
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Change {
+    /// Holds the path the wire was watched by
+    pub path: String,
+    /// Holds the time at which the new value was observed
+    pub time: Time,
+    /// Holds the value before the change
+    pub old: u64,
+    /// Holds the value after the change
+    pub new: u64,
+}
+
+/// Identifies a watch so it can be removed with [Simulator::unwatch](crate::Simulator::unwatch)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct WatchId(pub(crate) usize);
+
+/// A callback told about changes of a wire
+pub(crate) type Callback = Box<dyn FnMut(&Change) + Send>;
+
+/// The watches of a simulator, which its clones start without since callbacks cannot be cloned
+#[derive(Debug, Default)]
+pub(crate) struct Watches(pub(crate) Vec<Watch>);
+
+impl Clone for Watches {
+    fn clone(&self) -> Watches {
+        Watches::default()
+    }
+}
+
+/// A watched wire and the value it had when last observed
+pub(crate) struct Watch {
+    pub(crate) id: WatchId,
+    path: String,
+    nets: Vec<Net>,
+    value: u64,
+    callback: Callback,
+}
+
+impl fmt::Debug for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Watch")
+            .field("id", &self.id)
+            .field("path", &self.path)
+            .field("value", &self.value)
+            .finish()
+    }
+}
+
+impl Watch {
+    pub(crate) fn new(
+        id: WatchId,
+        path: &str,
+        nets: Vec<Net>,
+        values: &[bool],
+        callback: Callback,
+    ) -> Watch {
+        Watch {
+            id,
+            path: path.to_string(),
+            value: memory::read(&nets, values),
+            nets,
+            callback,
+        }
+    }
+
+    /// Call back if the wire changed since it was last observed
+    pub(crate) fn observe(&mut self, time: Time, values: &[bool]) {
+        let value = memory::read(&self.nets, values);
+        if value != self.value {
+            let change = Change {
+                path: self.path.clone(),
+                time,
+                old: self.value,
+                new: value,
+            };
+            self.value = value;
+            (self.callback)(&change);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::computer::Computer;
+    use crate::probe::Change;
+    use crate::{Project, Simulator};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn probes_parts() {
+        let project = Project::new();
+        let mut ram = Simulator::new(project.chip("RAM64").unwrap(), &project).unwrap();
+        ram.set("address", 3 * 8 + 5).unwrap();
+        ram.set("in", 77).unwrap();
+        ram.set("load", 1).unwrap();
+        ram.step();
+        assert_eq!(ram.get("RAM64/RAM8[3]/Register[5]/out").unwrap(), 77);
+        assert_eq!(ram.get("RAM8[3]/Register[5]/out").unwrap(), 77);
+        assert_eq!(
+            ram.probe("RAM8[3]/Register[5]").unwrap(),
+            vec![
+                ("in".to_string(), 77),
+                ("load".to_string(), 1),
+                ("out".to_string(), 77)
+            ]
+        );
+        assert!(ram.probe("RAM8[9]").is_err());
+
+        // the chip's own pins and internal wires
+        let pins: Vec<String> = ram
+            .probe("")
+            .unwrap()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert!(pins.contains(&"address".to_string()));
+    }
+
+    #[test]
+    fn watches_changes() {
+        let project = Project::new();
+        let mut computer = Computer::new(&project).unwrap();
+        // @7, D=A, then count D down to zero
+        computer.load(&[0x0007, 0xec10, 0x0002, 0xe391]).unwrap();
+        let changes = Arc::new(Mutex::new(vec![]));
+        let seen = changes.clone();
+        let simulator = computer.simulator_mut();
+        let id = simulator
+            .watch("CPU/DRegister/out", move |change: &Change| {
+                seen.lock().unwrap().push(change.clone())
+            })
+            .unwrap();
+        let _ = simulator.watch("CPU/ALU/zr", |_: &Change| {}).unwrap();
+        computer.run(6);
+        {
+            let changes = changes.lock().unwrap();
+            assert_eq!(
+                changes.iter().map(|change| change.new).collect::<Vec<_>>(),
+                vec![7, 6, 5]
+            );
+            assert_eq!(changes[0].old, 0);
+            assert_eq!(changes[0].path, "CPU/DRegister/out");
+            assert_eq!(changes[0].time.to_string(), "2");
+        }
+        computer.simulator_mut().unwatch(id);
+        computer.run(4);
+        assert_eq!(changes.lock().unwrap().len(), 3);
+        // clones start without watches, so simulations can move to other threads
+        let clone = computer.clone();
+        std::thread::spawn(move || {
+            let mut clone = clone;
+            clone.run(4);
+        })
+        .join()
+        .unwrap();
+        assert_eq!(changes.lock().unwrap().len(), 3);
+        assert!(computer
+            .simulator_mut()
+            .watch("CPU/nothing", |_: &Change| {})
+            .is_err());
+    }
+}
@@ -0,0 +1,170 @@
+//! Collections of chips that parts are resolved against
+
+use crate::flatten::Primitive;
+use crate::{builtins, parse_hdl, Chip, HDLParseError};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// A type that represents a nand2tetris project
+///
+/// Chips are looked up by name, first among the chips added to the project and then among the
+/// builtin chips shipped with this crate.  `Nand` and `DFF` are always primitives.
+#[derive(Debug, Clone)]
+pub struct Project {
+    chips: BTreeMap<String, Chip>,
+    builtins: BTreeMap<String, Chip>,
+    parse_errors: BTreeMap<String, HDLParseError>,
+    sources: BTreeMap<String, String>,
+}
+
+/// What a part name resolves to within a [Project]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolved<'a> {
+    /// A chip defined in HDL which can be expanded further
+    Chip(&'a Chip),
+    /// A primitive implemented natively by the simulator
+    Primitive(Primitive),
+}
+
+impl Default for Project {
+    fn default() -> Self {
+        Project::new()
+    }
+}
+
+impl Project {
+    /// Create a project containing only the builtin chips
+    pub fn new() -> Project {
+        Project {
+            chips: BTreeMap::new(),
+            builtins: builtins::library()
+                .into_iter()
+                .map(|chip| (chip.name.clone(), chip))
+                .collect(),
+            parse_errors: BTreeMap::new(),
+            sources: BTreeMap::new(),
+        }
+    }
+
+    /// Create a project from every `.hdl` file in a directory
+    ///
+    /// Files which fail to parse do not abort loading; their errors are available from [Project::parse_errors]
+    /// keyed by the file stem, and resolving that chip name falls back to the builtin library.
+    pub fn from_dir<P: AsRef<Path>>(path: P) -> io::Result<Project> {
+        let mut project = Project::new();
+        let mut entries = fs::read_dir(path)?.collect::<Result<Vec<_>, _>>()?;
+        entries.sort_by_key(|entry| entry.path());
+        for entry in entries {
+            let path = entry.path();
+            if path.extension() != Some("hdl".as_ref()) {
+                continue;
+            }
+            let stem = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_default();
+            if let Err(e) = project.add_hdl(&fs::read_to_string(&path)?) {
+                let _ = project.parse_errors.insert(stem, e);
+            }
+        }
+        Ok(project)
+    }
+
+    /// Parse HDL and add the resulting chip to the project, replacing any chip with the same name
+    pub fn add_hdl(&mut self, hdl: &str) -> Result<&Chip, HDLParseError> {
+        let chip = parse_hdl(hdl)?;
+        let name = chip.name.clone();
+        self.insert(chip);
+        let _ = self.sources.insert(name.clone(), hdl.to_string());
+        Ok(&self.chips[&name])
+    }
+
+    /// Add a chip to the project, replacing any chip with the same name
+    pub fn insert(&mut self, chip: Chip) {
+        let _ = self.parse_errors.remove(&chip.name);
+        let _ = self.sources.remove(&chip.name);
+        let _ = self.chips.insert(chip.name.clone(), chip);
+    }
+
+    /// HDL source of a chip, looked up like [Project::chip]
+    ///
+    /// Chips added with [Project::insert] rather than parsed from HDL have no source.
+    pub fn source(&self, name: &str) -> Option<&str> {
+        if self.chips.contains_key(name) {
+            self.sources.get(name).map(String::as_str)
+        } else {
+            builtins::source(name)
+        }
+    }
+
+    /// Look up a chip by name, falling back to the builtin library
+    pub fn chip(&self, name: &str) -> Option<&Chip> {
+        self.chips.get(name).or_else(|| self.builtins.get(name))
+    }
+
+    /// Chips added to the project, excluding builtins
+    pub fn chips(&self) -> impl Iterator<Item = &Chip> {
+        self.chips.values()
+    }
+
+    /// Errors for `.hdl` files that could not be parsed by [Project::from_dir], keyed by file stem
+    pub fn parse_errors(&self) -> &BTreeMap<String, HDLParseError> {
+        &self.parse_errors
+    }
+
+    /// Resolve a part name to either an HDL chip or a primitive
+    ///
+    /// `Nand` and `DFF` are always primitive.  Memory builtins are primitive unless the project provides its own
+    /// HDL for them, as project 3 does for `RAM16K`.
+    pub fn resolve(&self, name: &str) -> Option<Resolved<'_>> {
+        match Primitive::from_name(name) {
+            Some(primitive @ Primitive::Nand) | Some(primitive @ Primitive::DFF) => {
+                Some(Resolved::Primitive(primitive))
+            }
+            Some(primitive) => Some(
+                self.chips
+                    .get(name)
+                    .map(Resolved::Chip)
+                    .unwrap_or(Resolved::Primitive(primitive)),
+            ),
+            None => self.chip(name).map(Resolved::Chip),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::flatten::Primitive;
+    use crate::project::{Project, Resolved};
+
+    #[test]
+    fn project_chips_shadow_builtins() {
+        let mut project = Project::new();
+        assert_eq!(project.chip("Not").unwrap().parts[0].name, "Nand");
+        let _ = project
+            .add_hdl("CHIP Not { IN in; OUT out; PARTS: Nand(a=in, b=true, out=out); }")
+            .unwrap();
+        assert_eq!(
+            project.chip("Not").unwrap().parts[0].external[1].name,
+            "true"
+        );
+        assert_eq!(project.chips().count(), 1);
+    }
+
+    #[test]
+    fn resolves_primitives() {
+        let project = Project::new();
+        assert_eq!(
+            project.resolve("Nand"),
+            Some(Resolved::Primitive(Primitive::Nand))
+        );
+        assert_eq!(
+            project.resolve("RAM16K"),
+            Some(Resolved::Primitive(Primitive::RAM16K))
+        );
+        assert!(matches!(project.resolve("Mux"), Some(Resolved::Chip(_))));
+        assert_eq!(project.resolve("Missing"), None);
+    }
+}
@@ -1,12 +1,30 @@
-use crate::{Chip, HDLParseError};
+use crate::computer::Registers;
+use crate::fault::FaultReport;
+use crate::format::{Specifier, SpecifierError};
+use crate::generate::Vectors;
+use crate::limits::Limits;
+use crate::{
+    Chip, Computer, FlattenError, HDLParseError, KeyScript, Metrics, Project, SimulationError,
+};
 use pyo3::create_exception;
 use pyo3::prelude::*;
+use pyo3::types::PyBytes;
 
 create_exception!(
     nand2tetris_hdl_parser,
     PyHDLParseError,
     pyo3::exceptions::PyException
 );
+create_exception!(
+    nand2tetris_hdl_parser,
+    PyFlattenError,
+    pyo3::exceptions::PyException
+);
+create_exception!(
+    nand2tetris_hdl_parser,
+    PySimulationError,
+    pyo3::exceptions::PyException
+);
 
 // struct HDLParserErrorWrapper {
 //     details: String,
@@ -18,6 +36,66 @@ impl From<HDLParseError> for PyErr {
     }
 }
 
+impl From<FlattenError> for PyErr {
+    fn from(error: FlattenError) -> Self {
+        PyFlattenError::new_err(error.to_string())
+    }
+}
+
+impl From<SimulationError> for PyErr {
+    fn from(error: SimulationError) -> Self {
+        match error {
+            SimulationError::Flatten(error) => error.into(),
+            error => PySimulationError::new_err(error.to_string()),
+        }
+    }
+}
+
+/// Load the project that parts are resolved against, or only the builtin chips if there is no directory
+fn load_project(project_dir: Option<String>) -> PyResult<Project> {
+    match project_dir {
+        Some(dir) => Ok(Project::from_dir(dir)?),
+        None => Ok(Project::new()),
+    }
+}
+
+/// Parse limits given as a JSON object such as `{"cycles": 1000}`, every limit being off if there are none
+fn parse_limits(limits: Option<String>) -> PyResult<Limits> {
+    match limits {
+        Some(limits) => serde_json::from_str(&limits)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(format!("invalid limits: {}", e))),
+        None => Ok(Limits::default()),
+    }
+}
+
+/// Load a Hack program into a simulated computer, initialize its data memory and run it with scripted keys
+fn run_hack(
+    hack: String,
+    cycles: usize,
+    ram: Option<Vec<u16>>,
+    project_dir: Option<String>,
+    keys: Option<String>,
+    limits: Option<String>,
+) -> PyResult<Computer> {
+    let limits = parse_limits(limits)?;
+    let mut computer = Computer::new_with_limits(&load_project(project_dir)?, &limits)?;
+    computer.load_hack(&hack)?;
+    if let Some(keys) = keys {
+        computer.set_keys(KeyScript::parse(&keys)?);
+    }
+    for (address, &value) in ram.unwrap_or_default().iter().enumerate() {
+        computer.poke(address, value)?;
+    }
+    computer.run_with_limits(cycles, &limits)?;
+    Ok(computer)
+}
+
+/// Parse an `output-list` format specifier such as `%B1.16.1`
+fn parse_specifier(specifier: &str) -> PyResult<Specifier> {
+    specifier
+        .parse()
+        .map_err(|e: SpecifierError| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+}
 
 #[pymodule]
 fn nand2tetris_hdl_parser(py: Python, m: &PyModule) -> PyResult<()> {
@@ -26,8 +104,257 @@ fn nand2tetris_hdl_parser(py: Python, m: &PyModule) -> PyResult<()> {
         crate::parse_hdl(&hdl).map_err(|x| x.into())
     }
 
+    #[pyfn(m, "chip_metrics")]
+    pub fn chip_metrics_python(hdl: String, project_dir: Option<String>) -> PyResult<Metrics> {
+        let chip = crate::parse_hdl(&hdl)?;
+        Ok(crate::metrics(&chip, &load_project(project_dir)?)?)
+    }
+
+    #[pyfn(m, "fault_coverage")]
+    pub fn fault_coverage_python(
+        hdl: String,
+        vectors: Vec<Vec<u64>>,
+        project_dir: Option<String>,
+    ) -> PyResult<FaultReport> {
+        let chip = crate::parse_hdl(&hdl)?;
+        let netlist = crate::flatten(&chip, &load_project(project_dir)?)?;
+        Ok(crate::fault::vector_coverage(&netlist, &vectors)?)
+    }
+
+    #[pyfn(m, "differential_test")]
+    pub fn differential_test_python(
+        hdl: String,
+        steps: usize,
+        seed: u64,
+        reference_hdl: Option<String>,
+        project_dir: Option<String>,
+    ) -> PyResult<Option<String>> {
+        let chip = crate::parse_hdl(&hdl)?;
+        let project = load_project(project_dir)?;
+        // a builtin reference is built only from builtins, so that the project's own parts cannot hide bugs
+        let builtins = Project::new();
+        let (reference, reference_project) = match reference_hdl {
+            Some(hdl) => (crate::parse_hdl(&hdl)?, &project),
+            None => (
+                builtins.chip(&chip.name).cloned().ok_or_else(|| {
+                    PySimulationError::new_err(format!("no builtin reference for {}", chip.name))
+                })?,
+                &builtins,
+            ),
+        };
+        let divergence = crate::differential::differential_chips(
+            &chip,
+            &project,
+            &reference,
+            reference_project,
+            steps,
+            seed,
+        )?;
+        Ok(divergence.map(|divergence| divergence.to_string()))
+    }
+
+    #[pyfn(m, "generate_test")]
+    pub fn generate_test_python(
+        hdl: String,
+        vectors: Option<String>,
+        count: Option<usize>,
+        seed: Option<u64>,
+        project_dir: Option<String>,
+    ) -> PyResult<(String, String)> {
+        let chip = crate::parse_hdl(&hdl)?;
+        let vectors = match vectors.as_deref() {
+            None | Some("exhaustive") => Vectors::Exhaustive,
+            Some("corners") => Vectors::Corners,
+            Some("random") => Vectors::Random {
+                count: count.unwrap_or(100),
+                seed: seed.unwrap_or(0),
+            },
+            Some(vectors) => {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "unknown vectors {}",
+                    vectors
+                )))
+            }
+        };
+        let test = crate::generate::generate_test(&chip, &load_project(project_dir)?, vectors)?;
+        Ok((test.tst, test.cmp))
+    }
+
+    #[pyfn(m, "format_value")]
+    pub fn format_value_python(
+        value: i64,
+        specifier: String,
+        width: Option<usize>,
+    ) -> PyResult<String> {
+        Ok(parse_specifier(&specifier)?.format_signed(value, width.unwrap_or(16)))
+    }
+
+    #[pyfn(m, "format_text")]
+    pub fn format_text_python(text: String, specifier: String) -> PyResult<String> {
+        Ok(parse_specifier(&specifier)?.format_text(&text))
+    }
+
+    #[pyfn(m, "format_title")]
+    pub fn format_title_python(name: String, specifier: String) -> PyResult<String> {
+        Ok(parse_specifier(&specifier)?.title(&name))
+    }
+
+    #[pyfn(m, "compare_output")]
+    pub fn compare_output_python(
+        output: String,
+        expected: String,
+        full: Option<bool>,
+    ) -> PyResult<Option<String>> {
+        let parse = |text: &str| {
+            crate::compare::Table::parse(text)
+                .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))
+        };
+        let (output, expected) = (parse(&output)?, parse(&expected)?);
+        if full.unwrap_or(false) {
+            let diff = crate::compare::diff_tables(&output, &expected);
+            Ok(Some(diff.to_string()).filter(|_| !diff.is_empty()))
+        } else {
+            Ok(crate::compare::compare(&output, &expected).map(|mismatch| mismatch.to_string()))
+        }
+    }
+
+    #[pyfn(m, "run_project_tests")]
+    pub fn run_project_tests_python(
+        project_dir: String,
+        format: Option<String>,
+        limits: Option<String>,
+    ) -> PyResult<String> {
+        let limits = parse_limits(limits)?;
+        let report = crate::suite::run_project_tests_with_limits(project_dir, &limits)?;
+        match format.as_deref() {
+            None | Some("text") => Ok(report.to_string()),
+            Some("json") => Ok(report.to_json()),
+            Some("junit") => Ok(report.to_junit()),
+            Some(format) => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "unknown report format {}",
+                format
+            ))),
+        }
+    }
+
+    #[pyfn(m, "test_coverage")]
+    pub fn test_coverage_python(
+        hdl: String,
+        tst: String,
+        project_dir: Option<String>,
+        format: Option<String>,
+    ) -> PyResult<String> {
+        let mut project = load_project(project_dir.clone())?;
+        let chip = project.add_hdl(&hdl)?.clone();
+        let script = crate::test_script::parse_tst(&tst)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        let directory = project_dir.as_ref().map(std::path::Path::new);
+        let (_, coverage) =
+            crate::coverage::cover_script(&script, &chip, &project, directory, &Limits::default())?;
+        match format.as_deref() {
+            None | Some("text") => Ok(coverage.to_string()),
+            Some("json") => Ok(coverage.to_json()),
+            Some(format) => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "unknown report format {}",
+                format
+            ))),
+        }
+    }
+
+    #[pyfn(m, "mutation_test")]
+    pub fn mutation_test_python(
+        hdl: String,
+        tst: String,
+        cmp: String,
+        project_dir: Option<String>,
+        format: Option<String>,
+        limits: Option<String>,
+    ) -> PyResult<String> {
+        let invalid = |e: String| pyo3::exceptions::PyValueError::new_err(e);
+        let mut project = load_project(project_dir.clone())?;
+        let chip = project.add_hdl(&hdl)?.clone();
+        let script = crate::test_script::parse_tst(&tst).map_err(|e| invalid(e.to_string()))?;
+        let expected = crate::compare::Table::parse(&cmp).map_err(|e| invalid(e.to_string()))?;
+        let limits = parse_limits(limits)?;
+        let directory = project_dir.as_ref().map(std::path::Path::new);
+        let report = crate::mutation::mutation_test(
+            &chip, &project, &script, &expected, directory, &limits,
+        )?;
+        match format.as_deref() {
+            None | Some("text") => Ok(report.to_string()),
+            Some("json") => Ok(report.to_json()),
+            Some(format) => Err(invalid(format!("unknown report format {}", format))),
+        }
+    }
+
+    #[pyfn(m, "grade_project")]
+    pub fn grade_project_python(
+        project_dir: String,
+        policy: String,
+        format: Option<String>,
+    ) -> PyResult<String> {
+        let policy = crate::grade::Policy::parse(&policy)
+            .map_err(|e| pyo3::exceptions::PyValueError::new_err(e.to_string()))?;
+        let report = crate::grade::grade_project(project_dir, &policy)?;
+        match format.as_deref() {
+            None | Some("text") => Ok(report.to_string()),
+            Some("json") => Ok(report.to_json()),
+            Some(format) => Err(pyo3::exceptions::PyValueError::new_err(format!(
+                "unknown report format {}",
+                format
+            ))),
+        }
+    }
+
+    #[pyfn(m, "run_hack")]
+    pub fn run_hack_python(
+        hack: String,
+        cycles: usize,
+        ram: Option<Vec<u16>>,
+        project_dir: Option<String>,
+        keys: Option<String>,
+        limits: Option<String>,
+    ) -> PyResult<(Registers, Vec<u16>)> {
+        let computer = run_hack(hack, cycles, ram, project_dir, keys, limits)?;
+        let ram = (0..crate::computer::SCREEN)
+            .map(|address| computer.peek(address))
+            .collect::<Result<_, _>>()?;
+        Ok((computer.registers()?, ram))
+    }
+
+    #[pyfn(m, "run_hack_screen")]
+    // every argument is a keyword argument from Python
+    #[allow(clippy::too_many_arguments)]
+    pub fn run_hack_screen_python(
+        py: Python,
+        hack: String,
+        cycles: usize,
+        ram: Option<Vec<u16>>,
+        project_dir: Option<String>,
+        format: Option<String>,
+        keys: Option<String>,
+        limits: Option<String>,
+    ) -> PyResult<PyObject> {
+        let computer = run_hack(hack, cycles, ram, project_dir, keys, limits)?;
+        let screen = computer.screen()?;
+        let image = match format.as_deref() {
+            None => crate::screen::bitmap(screen),
+            Some("pbm") => crate::screen::to_pbm(screen),
+            Some("png") => crate::screen::to_png(screen),
+            Some(format) => {
+                return Err(pyo3::exceptions::PyValueError::new_err(format!(
+                    "unknown image format {}",
+                    format
+                )))
+            }
+        };
+        Ok(PyBytes::new(py, &image).into())
+    }
+
     // m.add_function(wrap_pyfunction!(parse_hdl, m)?)?;
     m.add("HDLParseError", py.get_type::<PyHDLParseError>())?;
+    m.add("FlattenError", py.get_type::<PyFlattenError>())?;
+    m.add("SimulationError", py.get_type::<PySimulationError>())?;
     Ok(())
 }
 
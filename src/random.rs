@@ -0,0 +1,34 @@
+//! Small deterministic pseudo random number generator
+//!
+//! Seeded runs must reproduce exactly across platforms and releases, so this uses splitmix64 rather than an
+//! external crate whose output could change between versions.
+
+/// A splitmix64 generator
+#[derive(Debug, Clone)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        Rng { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A random value that fits in `width` bits
+    pub(crate) fn bits(&mut self, width: usize) -> u64 {
+        let value = self.next_u64();
+        if width >= 64 {
+            value
+        } else {
+            value & ((1 << width) - 1)
+        }
+    }
+}
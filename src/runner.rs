@@ -0,0 +1,526 @@
+//! Running test scripts against a simulated chip
+//!
+//! A parsed [TestScript] drives a [Simulator] the way the official HardwareSimulator does: `set`, `eval`, `tick`
+//! and `tock` change the chip, `output-list` prints the header of the output table and `output` one of its rows.
+//! The table is laid out character for character like the official `.out` files, so it can be compared against
+//! the `.cmp` files shipped with the course.
+
+use crate::computer::parse_hack;
+use crate::flatten::flatten_with_limits;
+use crate::format::{Radix, Specifier};
+use crate::limits::Limits;
+use crate::simulator::{SimulationError, Simulator};
+use crate::test_script::{
+    parse_tst, Command, CommandKind, Comparison, Condition, Index, Operand, OutputColumn,
+    TestScript, Variable,
+};
+use crate::{Chip, Project};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use std::time::Instant;
+
+/// How columns are printed when the output list gives no format
+pub const DEFAULT_FORMAT: Specifier = Specifier {
+    radix: Radix::Binary,
+    left: 1,
+    len: 1,
+    right: 1,
+};
+
+/// Most clock cycles [run_tst] simulates, so a `while` loop that never ends fails instead of hanging
+pub const DEFAULT_CYCLES: usize = 1 << 20;
+
+/// What running a test script produced
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct TestRun {
+    /// Holds the output table, one line per `output-list` header or `output` row
+    pub output: String,
+    /// Holds the file named by `output-file`, if any
+    pub output_file: Option<String>,
+    /// Holds the file named by `compare-to`, if any
+    pub compare_to: Option<String>,
+    /// Holds the last message shown by `echo`, unless cleared since
+    pub echo: Option<String>,
+}
+
+/// Run a test script against a chip
+///
+/// Files the script loads into memories, such as `ROM32K load Max.hack`, are read from `directory`.  Nothing is
+/// written; the output table is returned with the name of the file the script would write it to.  Files must be
+/// named without any directory, so scripts fail with [SimulationError::InvalidScript] on names like `../Max.hack`.
+pub fn run_script(
+    script: &TestScript,
+    chip: &Chip,
+    project: &Project,
+    directory: Option<&Path>,
+) -> Result<TestRun, SimulationError> {
+    run_script_with_limits(script, chip, project, directory, &Limits::default())
+}
+
+/// Run a test script like [run_script], stopping with [SimulationError::LimitExceeded] once the chip or the run
+/// goes beyond `limits`
+///
+/// The wall-clock limit includes flattening the chip.
+pub fn run_script_with_limits(
+    script: &TestScript,
+    chip: &Chip,
+    project: &Project,
+    directory: Option<&Path>,
+    limits: &Limits,
+) -> Result<TestRun, SimulationError> {
+    let started = Instant::now();
+    let simulator = Simulator::from_netlist(flatten_with_limits(chip, project, limits)?)?;
+    run_simulator(script, simulator, directory, limits, started).map(|(run, _)| run)
+}
+
+/// Run a test script on an already prepared simulator, which is returned in its final state
+///
+/// `started` is when the wall-clock limit began counting.
+pub(crate) fn run_simulator(
+    script: &TestScript,
+    simulator: Simulator,
+    directory: Option<&Path>,
+    limits: &Limits,
+    started: Instant,
+) -> Result<(TestRun, Simulator), SimulationError> {
+    let mut runner = Runner {
+        simulator,
+        directory,
+        columns: vec![],
+        run: TestRun::default(),
+        limits: *limits,
+        started,
+    };
+    runner.execute(&script.commands)?;
+    Ok((runner.run, runner.simulator))
+}
+
+/// Read, parse and run a `.tst` file against a chip, then write the output file it names next to it
+///
+/// Returns the run and the path of the output file written, if the script names one.  Scripts simulating more than
+/// [DEFAULT_CYCLES] clock cycles fail with [SimulationError::LimitExceeded]; loops that evaluate the chip without
+/// ever ticking the clock are not bounded, so untrusted scripts should be run with [run_script_with_limits] and a
+/// time limit instead.
+pub fn run_tst(
+    path: &Path,
+    chip: &Chip,
+    project: &Project,
+) -> Result<(TestRun, Option<PathBuf>), SimulationError> {
+    let text = read(path)?;
+    let script = parse_tst(&text)
+        .map_err(|e| SimulationError::InvalidScript(format!("{}: {}", path.display(), e)))?;
+    let directory = path.parent().unwrap_or_else(|| Path::new(""));
+    let limits = Limits {
+        cycles: Some(DEFAULT_CYCLES),
+        ..Limits::default()
+    };
+    let run = run_script_with_limits(&script, chip, project, Some(directory), &limits)?;
+    let written = match &run.output_file {
+        Some(file) => {
+            let out = directory.join(file);
+            fs::write(&out, &run.output).map_err(|e| {
+                SimulationError::Io(format!("cannot write {}: {}", out.display(), e))
+            })?;
+            Some(out)
+        }
+        None => None,
+    };
+    Ok((run, written))
+}
+
+/// Fail unless a file named by a script is a plain name, so it can only refer to a file in the script's directory
+pub(crate) fn check_file_name(file: &str) -> Result<(), SimulationError> {
+    let mut components = Path::new(file).components();
+    match (components.next(), components.next()) {
+        (Some(Component::Normal(_)), None) => Ok(()),
+        _ => Err(SimulationError::InvalidScript(format!(
+            "{} is not a file in the script's directory",
+            file
+        ))),
+    }
+}
+
+fn read(path: &Path) -> Result<String, SimulationError> {
+    fs::read_to_string(path)
+        .map_err(|e| SimulationError::Io(format!("cannot read {}: {}", path.display(), e)))
+}
+
+/// The state of a script being run
+struct Runner<'a> {
+    simulator: Simulator,
+    directory: Option<&'a Path>,
+    columns: Vec<OutputColumn>,
+    run: TestRun,
+    limits: Limits,
+    started: Instant,
+}
+
+/// The value of a variable as an output column sees it
+enum Value {
+    /// A bus of the given width
+    Bits(u64, usize),
+    /// The simulated time
+    Text(String),
+}
+
+impl<'a> Runner<'a> {
+    fn execute(&mut self, commands: &[Command]) -> Result<(), SimulationError> {
+        for command in commands {
+            self.command(&command.kind)?;
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, kind: &CommandKind) -> Result<(), SimulationError> {
+        self.limits.check_time(self.started)?;
+        match kind {
+            CommandKind::Load(file) => {
+                let name = &self.simulator.netlist().name;
+                let stem = file
+                    .as_ref()
+                    .and_then(|file| Path::new(file).file_stem())
+                    .and_then(|stem| stem.to_str());
+                if let Some(stem) = stem.filter(|stem| stem != name) {
+                    return Err(SimulationError::InvalidScript(format!(
+                        "script loads {} but the chip is {}",
+                        stem, name
+                    )));
+                }
+            }
+            CommandKind::LoadMemory { memory, file } => {
+                check_file_name(file)?;
+                let directory = self.directory.ok_or_else(|| {
+                    SimulationError::InvalidScript(format!("no directory to load {} from", file))
+                })?;
+                let words = parse_hack(&read(&directory.join(file))?)?;
+                self.simulator.memory_mut(memory)?.load(&words);
+            }
+            CommandKind::OutputFile(file) => {
+                check_file_name(file)?;
+                self.run.output_file = Some(file.clone());
+            }
+            CommandKind::CompareTo(file) => {
+                check_file_name(file)?;
+                self.run.compare_to = Some(file.clone());
+            }
+            CommandKind::OutputList(columns) => {
+                self.columns = columns.clone();
+                let mut line = String::from("|");
+                for column in columns {
+                    let format = column.format.unwrap_or(DEFAULT_FORMAT);
+                    line.push_str(&format.title(&column.variable.to_string()));
+                    line.push('|');
+                }
+                self.run.output.push_str(&line);
+                self.run.output.push('\n');
+            }
+            CommandKind::Output => {
+                let mut line = String::from("|");
+                for column in &self.columns {
+                    let format = column.format.unwrap_or(DEFAULT_FORMAT);
+                    line.push_str(&match self.value(&column.variable)? {
+                        Value::Bits(value, width) => format.format(value, width),
+                        Value::Text(text) => format.format_text(&text),
+                    });
+                    line.push('|');
+                }
+                self.run.output.push_str(&line);
+                self.run.output.push('\n');
+            }
+            CommandKind::Set { variable, value } => self.set(variable, *value)?,
+            CommandKind::Eval => self.simulator.eval(),
+            CommandKind::Tick => self.simulator.tick(),
+            CommandKind::Tock => {
+                self.simulator.tock();
+                self.limits.check_cycles(self.simulator.time().cycle)?;
+            }
+            CommandKind::Echo(message) => self.run.echo = Some(message.clone()),
+            CommandKind::ClearEcho => self.run.echo = None,
+            CommandKind::Key(key) => {
+                self.simulator.memory_mut("Keyboard")?.poke(0, *key);
+                self.simulator.eval();
+            }
+            CommandKind::Repeat {
+                count: Some(count),
+                body,
+            } => {
+                for _ in 0..*count {
+                    self.execute(body)?;
+                }
+            }
+            CommandKind::Repeat { count: None, .. } => {
+                return Err(SimulationError::InvalidScript(
+                    "repeat without a count never finishes".to_string(),
+                ))
+            }
+            CommandKind::While { condition, body } => {
+                while self.holds(condition)? {
+                    self.execute(body)?;
+                    // a loop with an empty body checks the time here
+                    self.limits.check_time(self.started)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a variable names a word of a memory builtin, like `RAM16K[3]`
+    fn is_memory(&self, variable: &Variable) -> bool {
+        matches!(variable.index, Some(Index::At(_)))
+            && self.simulator.memory(&variable.name).is_ok()
+    }
+
+    fn value(&self, variable: &Variable) -> Result<Value, SimulationError> {
+        match variable.index {
+            None if variable.name == "time" && self.simulator.width("time").is_err() => {
+                Ok(Value::Text(self.simulator.time().to_string()))
+            }
+            Some(Index::At(address)) if self.is_memory(variable) => Ok(Value::Bits(
+                self.simulator.memory(&variable.name)?.peek(address) as u64,
+                16,
+            )),
+            // the state of a builtin part is what it outputs
+            Some(Index::Whole) => self.bits(&self.part_output(&variable.name)?),
+            // a register's only word, like `ARegister[0]`, unless a wire of that name is meant
+            Some(Index::At(0)) if self.simulator.width(&variable.name).is_err() => {
+                self.bits(&self.part_output(&variable.name)?)
+            }
+            _ => self.bits(&variable.to_string()),
+        }
+    }
+
+    fn bits(&self, name: &str) -> Result<Value, SimulationError> {
+        Ok(Value::Bits(
+            self.simulator.get(name)?,
+            self.simulator.width(name)?,
+        ))
+    }
+
+    /// The output of a part of the chip, named by its instance like `PC` or, anywhere in the hierarchy, by the chip
+    /// it is built from if only one part is, like the `ARegister` within a computer's CPU
+    fn part_output(&self, name: &str) -> Result<String, SimulationError> {
+        let output = format!("{}/out", name);
+        if self.simulator.width(&output).is_ok() {
+            return Ok(output);
+        }
+        let mut parts = self
+            .simulator
+            .netlist()
+            .instances
+            .iter()
+            .filter(|instance| instance.chip == name);
+        match (parts.next(), parts.next()) {
+            (Some(part), None) => Ok(format!("{}/out", part.path)),
+            _ => Err(SimulationError::UnknownPin(output)),
+        }
+    }
+
+    fn set(&mut self, variable: &Variable, value: i64) -> Result<(), SimulationError> {
+        match variable.index {
+            Some(Index::At(address)) if self.is_memory(variable) => {
+                self.simulator
+                    .memory_mut(&variable.name)?
+                    .poke(address, value as u16);
+                Ok(())
+            }
+            Some(Index::Whole) => Err(SimulationError::InvalidScript(format!(
+                "cannot set {}",
+                variable
+            ))),
+            // negative values are set in two's complement
+            _ => self.simulator.set(&variable.to_string(), value as u64),
+        }
+    }
+
+    fn holds(&self, condition: &Condition) -> Result<bool, SimulationError> {
+        let left = self.operand(&condition.left)?;
+        let right = self.operand(&condition.right)?;
+        Ok(match condition.comparison {
+            Comparison::Equal => left == right,
+            Comparison::NotEqual => left != right,
+            Comparison::Less => left < right,
+            Comparison::Greater => left > right,
+            Comparison::LessEqual => left <= right,
+            Comparison::GreaterEqual => left >= right,
+        })
+    }
+
+    /// The value of an operand as a number, signed for 16 bit buses as the official simulator reads them
+    fn operand(&self, operand: &Operand) -> Result<i64, SimulationError> {
+        match operand {
+            Operand::Constant(value) => Ok(*value),
+            Operand::Variable(variable) => match self.value(variable)? {
+                Value::Bits(value, 16) => Ok(value as u16 as i16 as i64),
+                Value::Bits(value, _) => Ok(value as i64),
+                Value::Text(_) => Err(SimulationError::InvalidScript(format!(
+                    "{} is not a number",
+                    variable
+                ))),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::limits::Limit;
+    use crate::runner::{run_script, run_tst, DEFAULT_CYCLES};
+    use crate::simulator::SimulationError;
+    use crate::test_script::parse_tst;
+    use crate::testing::TempProject;
+    use crate::Project;
+    use std::path::Path;
+
+    #[test]
+    fn writes_output_table() {
+        let project = Project::new();
+        let script = parse_tst(
+            "load Xor.hdl, output-file Xor.out, compare-to Xor.cmp,
+             output-list a%B3.1.3 b%B3.1.3 out%B3.1.3;
+             set a 0, set b 0, eval, output;
+             set a 0, set b 1, eval, output;
+             set a 1, set b 0, eval, output;
+             set a 1, set b 1, eval, output;",
+        )
+        .unwrap();
+        let run = run_script(&script, project.chip("Xor").unwrap(), &project, None).unwrap();
+        assert_eq!(
+            run.output,
+            "|   a   |   b   |  out  |\n\
+             |   0   |   0   |   0   |\n\
+             |   0   |   1   |   1   |\n\
+             |   1   |   0   |   1   |\n\
+             |   1   |   1   |   0   |\n"
+        );
+        assert_eq!(run.output_file.as_deref(), Some("Xor.out"));
+        assert_eq!(run.compare_to.as_deref(), Some("Xor.cmp"));
+
+        for escape in &[
+            "output-file ../Xor.out;",
+            "compare-to /tmp/Xor.cmp;",
+            "ROM32K load a/b.hack;",
+        ] {
+            assert!(matches!(
+                run_script(
+                    &parse_tst(escape).unwrap(),
+                    project.chip("Xor").unwrap(),
+                    &project,
+                    None
+                ),
+                Err(SimulationError::InvalidScript(_))
+            ));
+        }
+
+        let wrong = parse_tst("load And.hdl;").unwrap();
+        assert!(matches!(
+            run_script(&wrong, project.chip("Xor").unwrap(), &project, None),
+            Err(SimulationError::InvalidScript(_))
+        ));
+    }
+
+    #[test]
+    fn runs_clocked_chips() {
+        let project = Project::new();
+        let script = parse_tst(
+            "output-list time%S1.4.1 in%D1.6.1 load%B1.1.1 out%D1.6.1 out%X1.4.1;
+             set in -1, set load 1, tick, output; tock, output;
+             set in 300, set load 0;
+             while out <> 0 { set in 0, set load 1, tick, tock; }
+             output;",
+        )
+        .unwrap();
+        let run = run_script(&script, project.chip("Register").unwrap(), &project, None).unwrap();
+        assert_eq!(
+            run.output,
+            "| time |   in   |loa|  out   | out  |\n\
+             | 0+   |     -1 | 1 |      0 | 0000 |\n\
+             | 1    |     -1 | 1 |     -1 | FFFF |\n\
+             | 2    |      0 | 1 |      0 | 0000 |\n"
+        );
+    }
+
+    #[test]
+    fn shows_computer_registers() {
+        let project = Project::new();
+        // the start of the official ComputerMax.tst, whose output list reads the registers inside the CPU
+        let script = parse_tst(
+            "load Computer.hdl,
+             output-list time%S1.4.1 reset%B2.1.2 ARegister[0]%D1.7.1 DRegister[0]%D1.7.1 PC[]%D0.5.0
+                 RAM16K[0]%D1.7.1 RAM16K[1]%D1.7.1 RAM16K[2]%D1.7.1;
+             ROM32K load Max.hack,
+             set RAM16K[0] 3, set RAM16K[1] 5, output;
+             repeat 4 { tick, tock, output; }",
+        )
+        .unwrap();
+        let computer = project.chip("Computer").unwrap();
+        let run = run_script(&script, computer, &project, Some(Path::new("test_cases"))).unwrap();
+        assert_eq!(
+            run.output,
+            "| time |reset|ARegister|DRegister|PC[] |RAM16K[0]|RAM16K[1]|RAM16K[2]|\n\
+             | 0    |  0  |       0 |       0 |    0|       3 |       5 |       0 |\n\
+             | 1    |  0  |       0 |       0 |    1|       3 |       5 |       0 |\n\
+             | 2    |  0  |       0 |       3 |    2|       3 |       5 |       0 |\n\
+             | 3    |  0  |       1 |       3 |    3|       3 |       5 |       0 |\n\
+             | 4    |  0  |       1 |      -2 |    4|       3 |       5 |       0 |\n"
+        );
+    }
+
+    #[test]
+    fn stops_endless_files() {
+        let directory = TempProject::new("runner", &[], &[]);
+        directory.write("Not.tst", "set in 0; while out = 1 { tick, tock; }");
+        let project = Project::new();
+        assert!(matches!(
+            run_tst(
+                &directory.path().join("Not.tst"),
+                project.chip("Not").unwrap(),
+                &project
+            ),
+            Err(SimulationError::LimitExceeded(Limit::Cycles(
+                DEFAULT_CYCLES
+            )))
+        ));
+    }
+
+    #[test]
+    fn loads_programs() {
+        let project = Project::new();
+        // Max.hack leaves the larger of RAM[0] and RAM[1] in RAM[2]
+        let script = parse_tst(
+            "load Computer.hdl, ROM32K load Max.hack,
+             output-list RAM16K[0]%D2.6.2 RAM16K[1]%D2.6.2 RAM16K[2]%D2.6.2;
+             set RAM16K[0] 3, set RAM16K[1] -5;
+             repeat 20 { tick, tock; }
+             output;",
+        )
+        .unwrap();
+        let computer = project.chip("Computer").unwrap();
+        let run = run_script(&script, computer, &project, Some(Path::new("test_cases"))).unwrap();
+        assert_eq!(
+            run.output.lines().nth(1),
+            Some("|       3  |      -5  |       3  |")
+        );
+        assert!(run_script(&script, computer, &project, None).is_err());
+    }
+
+    #[test]
+    fn presses_keys() {
+        let project = Project::new();
+        let script = parse_tst(
+            "output-list out%D1.6.1;
+             set address 24576, key A, output; key newline, output; key release, output;",
+        )
+        .unwrap();
+        let memory = project.chip("Memory").unwrap();
+        let run = run_script(&script, memory, &project, None).unwrap();
+        assert_eq!(
+            run.output,
+            "|  out   |\n|     65 |\n|    128 |\n|      0 |\n"
+        );
+        assert!(matches!(
+            run_script(&script, project.chip("Not").unwrap(), &project, None),
+            Err(SimulationError::UnknownPin(_))
+        ));
+    }
+}
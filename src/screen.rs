@@ -0,0 +1,146 @@
+//! Images of the Hack screen
+//!
+//! The `Screen` builtin maps a 512 by 256 black and white display onto 8K words: each row of pixels is 32
+//! consecutive words and the least significant bit of a word is its leftmost pixel.  These functions render such
+//! a memory, typically [Computer::screen](crate::Computer::screen) after a run, without the official GUI.
+
+use crate::memory::Memory;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Width of the screen in pixels
+pub const WIDTH: usize = 512;
+
+/// Height of the screen in pixels
+pub const HEIGHT: usize = 256;
+
+/// Bytes in one row of a packed bitmap
+const ROW: usize = WIDTH / 8;
+
+/// Pack the screen into rows of bytes, top row first, with the leftmost pixel of each byte in its most
+/// significant bit and black pixels set
+pub fn bitmap(screen: &Memory) -> Vec<u8> {
+    let mut bitmap = Vec::with_capacity(ROW * HEIGHT);
+    for address in 0..WIDTH * HEIGHT / 16 {
+        let word = screen.peek(address);
+        bitmap.push((word as u8).reverse_bits());
+        bitmap.push(((word >> 8) as u8).reverse_bits());
+    }
+    bitmap
+}
+
+/// Render the screen as a binary portable bitmap (`P4`)
+pub fn to_pbm(screen: &Memory) -> Vec<u8> {
+    let mut pbm = format!("P4\n{} {}\n", WIDTH, HEIGHT).into_bytes();
+    pbm.extend(bitmap(screen));
+    pbm
+}
+
+/// Render the screen as a one bit grayscale PNG
+pub fn to_png(screen: &Memory) -> Vec<u8> {
+    // every row starts with filter type 0, and PNG uses zero for black
+    let mut raw = Vec::with_capacity((ROW + 1) * HEIGHT);
+    for row in bitmap(screen).chunks(ROW) {
+        raw.push(0);
+        raw.extend(row.iter().map(|byte| !byte));
+    }
+
+    // a zlib stream holding a single uncompressed deflate block
+    let mut zlib = vec![0x78, 0x01, 0x01];
+    zlib.extend(&(raw.len() as u16).to_le_bytes());
+    zlib.extend(&(!(raw.len() as u16)).to_le_bytes());
+    zlib.extend(&raw);
+    zlib.extend(&adler32(&raw).to_be_bytes());
+
+    let mut header = vec![];
+    header.extend(&(WIDTH as u32).to_be_bytes());
+    header.extend(&(HEIGHT as u32).to_be_bytes());
+    // bit depth 1, grayscale, deflate, no filtering beyond type 0, no interlacing
+    header.extend(&[1, 0, 0, 0, 0]);
+
+    let mut png = vec![0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"IDAT", &zlib);
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+/// Write the screen to a file, as a PNG if the path ends in `.png` and as a portable bitmap otherwise
+pub fn save<P: AsRef<Path>>(screen: &Memory, path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    let image = match path.extension() {
+        Some(extension) if extension.eq_ignore_ascii_case("png") => to_png(screen),
+        _ => to_pbm(screen),
+    };
+    fs::write(path, image)
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend(kind);
+    png.extend(data);
+    let crc = crc32(&png[start..]);
+    png.extend(&crc.to_be_bytes());
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        (0..8).fold(crc ^ byte as u32, |crc, _| {
+            if crc & 1 == 1 {
+                crc >> 1 ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            }
+        })
+    })
+}
+
+fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::computer::{Computer, SCREEN};
+    use crate::screen::{adler32, bitmap, crc32, to_pbm, to_png, HEIGHT};
+    use crate::Project;
+
+    #[test]
+    fn renders_pixels() {
+        let project = Project::new();
+        let mut computer = Computer::new(&project).unwrap();
+        // @SCREEN, M=-1
+        computer.load(&[0x4000, 0xee88]).unwrap();
+        computer.run(2);
+        // leftmost pixel of the second row, rightmost pixel of the last row
+        computer.poke(SCREEN + 32, 1).unwrap();
+        computer.poke(SCREEN + 32 * HEIGHT - 1, 0x8000).unwrap();
+
+        let bitmap = bitmap(computer.screen().unwrap());
+        assert_eq!(bitmap.len(), 512 * 256 / 8);
+        assert_eq!(&bitmap[..3], &[0xff, 0xff, 0]);
+        assert_eq!(bitmap[64], 0x80);
+        assert_eq!(bitmap[bitmap.len() - 1], 0x01);
+
+        let pbm = to_pbm(computer.screen().unwrap());
+        assert!(pbm.starts_with(b"P4\n512 256\n"));
+        assert_eq!(&pbm[11..], &bitmap[..]);
+    }
+
+    #[test]
+    fn encodes_png() {
+        assert_eq!(crc32(b"IEND"), 0xae42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11e6_0398);
+        let project = Project::new();
+        let computer = Computer::new(&project).unwrap();
+        let png = to_png(computer.screen().unwrap());
+        assert!(png.starts_with(b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR\0\0\x02\0\0\0\x01\0\x01\0"));
+        assert!(png.ends_with(b"\0\0\0\0IEND\xae\x42\x60\x82"));
+    }
+}
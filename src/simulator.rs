@@ -0,0 +1,699 @@
+//! Simulation of parsed chips
+//!
+//! A [Simulator] flattens a chip into a [Netlist] once, orders its cells so that every gate is evaluated after
+//! the gates feeding it, and then evaluates that fixed order each time [Simulator::eval] is called.
+//!
+//! Clocked chips follow the official hardware simulator: [Simulator::tick] is the rising edge, where every flip-flop
+//! samples its input, and [Simulator::tock] is the falling edge, where the sampled values appear on the outputs.
+
+use crate::coverage::Toggles;
+use crate::flatten::{flatten, FlattenError, Net, Netlist, Primitive, TRUE};
+use crate::limits::Limit;
+use crate::memory::{self, Memory, MemoryCell};
+use crate::probe::{Change, Watch, WatchId, Watches};
+use crate::waveform::Waveform;
+use crate::{Chip, Pin, Project};
+use core::fmt;
+use std::error::Error;
+
+/// Error returned when a chip cannot be simulated
+#[derive(Debug, Clone)]
+pub enum SimulationError {
+    /// The chip hierarchy could not be flattened
+    Flatten(FlattenError),
+    /// No pin or wire has the given name
+    UnknownPin(String),
+    /// The named pin cannot be set because it is not an input of the chip
+    NotAnInput(String),
+    /// The chip contains clocked parts or memories where only combinational logic is supported
+    NotCombinational(String),
+    /// An input vector does not match the inputs of the chip
+    InvalidVector(String),
+    /// The chip has too many input bits, given here, to enumerate every combination
+    TooManyInputs(usize),
+    /// Two chips that were expected to share an interface have different pins
+    InterfaceMismatch(String),
+    /// No memory builtin matches the given name
+    UnknownMemory(String),
+    /// A machine language program could not be loaded
+    InvalidProgram(String),
+    /// A script driving the simulation is malformed or cannot be carried out
+    InvalidScript(String),
+    /// A file the simulation reads or writes could not be accessed
+    Io(String),
+    /// The simulation needed more of a resource than its [Limits](crate::limits::Limits) allow
+    LimitExceeded(Limit),
+}
+
+impl fmt::Display for SimulationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SimulationError::Flatten(e) => write!(f, "{}", e),
+            SimulationError::UnknownPin(name) => write!(f, "unknown pin or wire {}", name),
+            SimulationError::NotAnInput(name) => write!(f, "{} is not an input pin", name),
+            SimulationError::NotCombinational(details) => {
+                write!(f, "chip is not combinational: {}", details)
+            }
+            SimulationError::InvalidVector(details) => {
+                write!(f, "invalid input vector: {}", details)
+            }
+            SimulationError::TooManyInputs(bits) => {
+                write!(f, "{} input bits are too many to enumerate", bits)
+            }
+            SimulationError::InterfaceMismatch(details) => write!(f, "{}", details),
+            SimulationError::UnknownMemory(name) => write!(f, "unknown memory {}", name),
+            SimulationError::InvalidProgram(details) => write!(f, "invalid program: {}", details),
+            SimulationError::InvalidScript(details) => write!(f, "invalid script: {}", details),
+            SimulationError::Io(details) => write!(f, "{}", details),
+            SimulationError::LimitExceeded(limit) => write!(f, "limit exceeded: {}", limit),
+        }
+    }
+}
+
+impl Error for SimulationError {}
+
+impl From<FlattenError> for SimulationError {
+    fn from(error: FlattenError) -> Self {
+        SimulationError::Flatten(error)
+    }
+}
+
+/// A single step of the precomputed evaluation order
+#[derive(Debug, Clone, Copy)]
+enum Op {
+    Nand(Net, Net, Net),
+    Memory(usize),
+    /// hold a net at a value, injecting a stuck-at fault
+    Force(Net, bool),
+}
+
+/// Simulated time, counted in clock cycles
+///
+/// Displays like the official simulator's `time` variable: `3` before the fourth tick and `3+` between that tick
+/// and the following tock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default)]
+pub struct Time {
+    /// Holds the number of completed clock cycles
+    pub cycle: usize,
+    /// Holds whether the clock has ticked but not yet tocked
+    pub ticked: bool,
+}
+
+impl fmt::Display for Time {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.cycle, if self.ticked { "+" } else { "" })
+    }
+}
+
+/// A type that simulates a chip
+#[derive(Debug, Clone)]
+pub struct Simulator {
+    netlist: Netlist,
+    ops: Vec<Op>,
+    values: Vec<bool>,
+    /// input and output net of every flip-flop
+    dffs: Vec<(Net, Net)>,
+    /// values sampled by the flip-flops on the last tick
+    latched: Vec<bool>,
+    memories: Vec<MemoryCell>,
+    time: Time,
+    waveform: Option<Waveform>,
+    toggles: Option<Toggles>,
+    watches: Watches,
+    next_watch: usize,
+    /// values that stuck inputs and flip-flop outputs would have without their faults
+    displaced: Vec<(Net, bool)>,
+}
+
+impl Simulator {
+    /// Flatten a chip, resolving its parts against a project, and prepare it for simulation
+    pub fn new(chip: &Chip, project: &Project) -> Result<Simulator, SimulationError> {
+        Simulator::from_netlist(flatten(chip, project)?)
+    }
+
+    /// Prepare an already flattened chip for simulation
+    pub fn from_netlist(netlist: Netlist) -> Result<Simulator, SimulationError> {
+        let mut memories = vec![];
+        let ops = netlist
+            .topological_order()?
+            .into_iter()
+            .filter_map(|index| {
+                let cell = &netlist.cells[index];
+                match cell.primitive {
+                    Primitive::Nand => Some(Op::Nand(
+                        cell.inputs[0][0],
+                        cell.inputs[1][0],
+                        cell.outputs[0][0],
+                    )),
+                    // a flip-flop's output only changes on a clock edge
+                    Primitive::DFF => None,
+                    _ => {
+                        memories.push(MemoryCell::new(cell));
+                        Some(Op::Memory(memories.len() - 1))
+                    }
+                }
+            })
+            .collect();
+        let dffs: Vec<_> = netlist
+            .cells
+            .iter()
+            .filter(|cell| cell.primitive == Primitive::DFF)
+            .map(|cell| (cell.inputs[0][0], cell.outputs[0][0]))
+            .collect();
+        let mut values = vec![false; netlist.nets];
+        values[TRUE] = true;
+        let mut simulator = Simulator {
+            netlist,
+            ops,
+            values,
+            latched: vec![false; dffs.len()],
+            dffs,
+            memories,
+            time: Time::default(),
+            waveform: None,
+            toggles: None,
+            watches: Watches::default(),
+            next_watch: 0,
+            displaced: vec![],
+        };
+        simulator.propagate();
+        Ok(simulator)
+    }
+
+    /// The flattened chip being simulated
+    pub fn netlist(&self) -> &Netlist {
+        &self.netlist
+    }
+
+    /// Set an input bus, or a slice of one such as `a[0..3]`, to a value
+    ///
+    /// Bits of `value` beyond the width of the bus are ignored.  Outputs do not change until [Simulator::eval]
+    /// is called.
+    pub fn set(&mut self, name: &str, value: u64) -> Result<(), SimulationError> {
+        let pin = parse_name(name)?;
+        let bus = match self.netlist.input(&pin.name) {
+            Some(bus) => bus,
+            None if self.netlist.wire(&pin.name).is_some() => {
+                return Err(SimulationError::NotAnInput(name.to_string()))
+            }
+            None => return Err(SimulationError::UnknownPin(name.to_string())),
+        };
+        let nets = slice(&bus.nets, pin.range(), name)?;
+        for (bit, &net) in nets.iter().enumerate() {
+            self.values[net] = bit < 64 && value >> bit & 1 == 1;
+        }
+        for (net, value) in self.displaced.iter_mut() {
+            if nets.contains(net) {
+                *value = self.values[*net];
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a pin or internal wire by name or hierarchical path, optionally sliced like `out[0..7]`
+    pub fn get(&self, name: &str) -> Result<u64, SimulationError> {
+        Ok(self.read(self.nets(name)?))
+    }
+
+    /// Width in bits of a pin or internal wire
+    pub fn width(&self, name: &str) -> Result<usize, SimulationError> {
+        Ok(self.nets(name)?.len())
+    }
+
+    /// Nets of a pin or internal wire, named as for [Simulator::get]
+    fn nets(&self, name: &str) -> Result<&[Net], SimulationError> {
+        let pin = parse_name(name)?;
+        let nets = self
+            .netlist
+            .wire(&pin.name)
+            .ok_or_else(|| SimulationError::UnknownPin(name.to_string()))?;
+        slice(nets, pin.range(), name)
+    }
+
+    /// Propagate the current inputs through every combinational gate
+    pub fn eval(&mut self) {
+        self.propagate();
+        self.sample();
+    }
+
+    fn propagate(&mut self) {
+        for op in &self.ops {
+            match *op {
+                Op::Nand(a, b, out) => self.values[out] = !(self.values[a] && self.values[b]),
+                Op::Memory(index) => self.memories[index].eval(&mut self.values),
+                Op::Force(net, value) => self.values[net] = value,
+            }
+        }
+    }
+
+    /// Rising clock edge: evaluate, then let every clocked part sample its inputs
+    ///
+    /// Outputs of clocked parts do not change until [Simulator::tock].
+    pub fn tick(&mut self) {
+        self.propagate();
+        for (latch, &(input, _)) in self.latched.iter_mut().zip(&self.dffs) {
+            *latch = self.values[input];
+        }
+        for memory in self.memories.iter_mut() {
+            memory.tick(&self.values);
+        }
+        self.time.ticked = true;
+        self.sample();
+    }
+
+    /// Falling clock edge: clocked parts output the values sampled on the last tick, then the chip is evaluated
+    pub fn tock(&mut self) {
+        if self.time.ticked {
+            for (&latch, &(_, output)) in self.latched.iter().zip(&self.dffs) {
+                self.values[output] = latch;
+            }
+            for (net, value) in self.displaced.iter_mut() {
+                if let Some(index) = self.dffs.iter().position(|&(_, output)| output == *net) {
+                    *value = self.latched[index];
+                }
+            }
+            for memory in self.memories.iter_mut() {
+                memory.tock();
+            }
+            self.time.cycle += 1;
+            self.time.ticked = false;
+        }
+        self.propagate();
+        self.sample();
+    }
+
+    /// Run one full clock cycle, a [Simulator::tick] followed by a [Simulator::tock]
+    pub fn step(&mut self) {
+        self.tick();
+        self.tock();
+    }
+
+    /// The current simulated time
+    pub fn time(&self) -> Time {
+        self.time
+    }
+
+    /// Hold every bit of a pin or internal wire, named as for [Simulator::get], stuck at a value
+    ///
+    /// The fault takes effect from the next evaluation and lasts until [Simulator::clear_faults].
+    pub fn stick(&mut self, name: &str, value: bool) -> Result<(), SimulationError> {
+        for net in self.nets(name)?.to_vec() {
+            self.stick_net(net, value);
+        }
+        Ok(())
+    }
+
+    /// Hold a single net stuck at a value
+    pub(crate) fn stick_net(&mut self, net: Net, value: bool) {
+        let memories = &self.memories;
+        let driver = self.ops.iter().position(|op| match *op {
+            Op::Nand(_, _, out) => out == net,
+            Op::Memory(index) => memories[index].drives(net),
+            Op::Force(..) => false,
+        });
+        // inputs and flip-flop outputs are forced before anything reads them, gate outputs as soon as written
+        match driver {
+            Some(driver) => self.ops.insert(driver + 1, Op::Force(net, value)),
+            None if self
+                .displaced
+                .iter()
+                .all(|&(displaced, _)| displaced != net) =>
+            {
+                self.displaced.push((net, self.values[net]))
+            }
+            None => {}
+        }
+        self.ops.insert(0, Op::Force(net, value));
+    }
+
+    /// Remove every fault injected with [Simulator::stick]
+    ///
+    /// Outputs do not change until [Simulator::eval] is called.
+    pub fn clear_faults(&mut self) {
+        self.ops.retain(|op| !matches!(op, Op::Force(..)));
+        for (net, value) in self.displaced.drain(..) {
+            self.values[net] = value;
+        }
+    }
+
+    /// Start recording pins or internal wires, named as for [Simulator::get], after every evaluation and clock edge
+    ///
+    /// With no names, every input and output of the chip is recorded.  Any earlier recording is discarded.
+    pub fn record(&mut self, signals: &[&str]) -> Result<(), SimulationError> {
+        let signals = if signals.is_empty() {
+            self.netlist
+                .inputs
+                .iter()
+                .chain(&self.netlist.outputs)
+                .map(|bus| (bus.name.clone(), bus.nets.clone()))
+                .collect()
+        } else {
+            signals
+                .iter()
+                .map(|&name| Ok((name.to_string(), self.nets(name)?.to_vec())))
+                .collect::<Result<_, SimulationError>>()?
+        };
+        self.waveform = Some(Waveform::new(&self.netlist.name, signals));
+        self.sample();
+        Ok(())
+    }
+
+    /// The signals recorded since [Simulator::record] was called
+    pub fn waveform(&self) -> Option<&Waveform> {
+        self.waveform.as_ref()
+    }
+
+    /// Stop recording and return what was recorded
+    pub fn stop_recording(&mut self) -> Option<Waveform> {
+        self.waveform.take()
+    }
+
+    /// Start noting which nets rise and which fall, comparing their values after every evaluation and clock edge
+    ///
+    /// Any earlier record is discarded.  Nets are compared against their current values first.
+    pub fn record_toggles(&mut self) {
+        self.toggles = Some(Toggles::new(&self.values));
+    }
+
+    /// The nets that have risen or fallen since [Simulator::record_toggles] was called
+    pub fn toggles(&self) -> Option<&Toggles> {
+        self.toggles.as_ref()
+    }
+
+    /// Values of the pins of a part, or of the chip itself for an empty path, by name
+    ///
+    /// Parts are named by hierarchical path like `RAM8[3]/Register[5]`, where the index tells apart parts of the
+    /// same kind used more than once by their parent.  Wires internal to the part are listed with its pins.
+    pub fn probe(&self, path: &str) -> Result<Vec<(String, u64)>, SimulationError> {
+        let root = &self.netlist.name;
+        let prefixes = if path.is_empty() {
+            vec![format!("{}/", root)]
+        } else {
+            vec![format!("{}/", path), format!("{}/{}/", root, path)]
+        };
+        for prefix in prefixes {
+            let pins: Vec<(String, u64)> = self
+                .netlist
+                .wires
+                .range(prefix.clone()..)
+                .take_while(|(wire, _)| wire.starts_with(&prefix))
+                .filter(|(wire, _)| !wire[prefix.len()..].contains('/'))
+                .map(|(wire, nets)| (wire[prefix.len()..].to_string(), self.read(nets)))
+                .collect();
+            if !pins.is_empty() {
+                return Ok(pins);
+            }
+        }
+        Err(SimulationError::UnknownPin(path.to_string()))
+    }
+
+    /// Call back whenever a pin or internal wire, named as for [Simulator::get], changes value
+    ///
+    /// Changes are noticed after every [Simulator::eval], [Simulator::tick] and [Simulator::tock].  Clones of the
+    /// simulator start without watches.
+    pub fn watch<F: FnMut(&Change) + Send + 'static>(
+        &mut self,
+        name: &str,
+        callback: F,
+    ) -> Result<WatchId, SimulationError> {
+        let nets = self.nets(name)?.to_vec();
+        let id = WatchId(self.next_watch);
+        self.next_watch += 1;
+        self.watches
+            .0
+            .push(Watch::new(id, name, nets, &self.values, Box::new(callback)));
+        Ok(id)
+    }
+
+    /// Stop calling back for a watch
+    pub fn unwatch(&mut self, id: WatchId) {
+        self.watches.0.retain(|watch| watch.id != id);
+    }
+
+    fn sample(&mut self) {
+        if let Some(waveform) = self.waveform.as_mut() {
+            waveform.sample(self.time, &self.values);
+        }
+        if let Some(toggles) = self.toggles.as_mut() {
+            toggles.sample(&self.values);
+        }
+        for watch in self.watches.0.iter_mut() {
+            watch.observe(self.time, &self.values);
+        }
+    }
+
+    /// Contents of a memory builtin
+    ///
+    /// Memories are named by hierarchical path, such as `Computer/Memory/RAM16K` or `Memory/RAM16K`, or simply by
+    /// builtin name (`RAM16K`, `ROM32K`, `Screen` or `Keyboard`) when the chip contains only one of that kind.
+    pub fn memory(&self, name: &str) -> Result<&Memory, SimulationError> {
+        let index = self.find_memory(name)?;
+        Ok(&self.memories[index].contents)
+    }
+
+    /// Mutable contents of a memory builtin, named as for [Simulator::memory]
+    ///
+    /// Outputs reading from the memory do not change until [Simulator::eval] is called.
+    pub fn memory_mut(&mut self, name: &str) -> Result<&mut Memory, SimulationError> {
+        let index = self.find_memory(name)?;
+        Ok(&mut self.memories[index].contents)
+    }
+
+    fn find_memory(&self, name: &str) -> Result<usize, SimulationError> {
+        let qualified = format!("{}/{}", self.netlist.name, name);
+        let by_path = self
+            .memories
+            .iter()
+            .position(|memory| memory.path == name || memory.path == qualified);
+        let mut by_kind = self
+            .memories
+            .iter()
+            .enumerate()
+            .filter(|(_, memory)| memory.primitive.name() == name)
+            .map(|(index, _)| index);
+        match (by_path, by_kind.next(), by_kind.next()) {
+            (Some(index), _, _) | (None, Some(index), None) => Ok(index),
+            _ => Err(SimulationError::UnknownMemory(name.to_string())),
+        }
+    }
+
+    /// Read a group of nets as an unsigned number, least significant bit first
+    fn read(&self, nets: &[Net]) -> u64 {
+        memory::read(nets, &self.values)
+    }
+}
+
+/// Parse a pin reference such as `a` or `a[0..3]` into a [Pin]
+fn parse_name(name: &str) -> Result<Pin, SimulationError> {
+    let open = match name.find('[') {
+        Some(open) if name.ends_with(']') => open,
+        _ => {
+            return Ok(Pin {
+                name: name.to_string(),
+                start: -1,
+                end: -1,
+            })
+        }
+    };
+    let invalid = || SimulationError::UnknownPin(name.to_string());
+    let mut bounds = name[open + 1..name.len() - 1].splitn(2, "..");
+    let start = bounds
+        .next()
+        .and_then(|start| start.trim().parse().ok())
+        .ok_or_else(invalid)?;
+    let end = match bounds.next() {
+        Some(end) => end.trim().parse().map_err(|_| invalid())?,
+        None => start,
+    };
+    Ok(Pin {
+        name: name[..open].to_string(),
+        start,
+        end,
+    })
+}
+
+fn slice<'a>(
+    nets: &'a [Net],
+    range: Option<(usize, usize)>,
+    name: &str,
+) -> Result<&'a [Net], SimulationError> {
+    match range {
+        None => Ok(nets),
+        Some((start, end)) if start <= end && end < nets.len() => Ok(&nets[start..=end]),
+        Some(_) => Err(SimulationError::UnknownPin(name.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulator::{SimulationError, Simulator};
+    use crate::{parse_hdl, Project};
+
+    #[test]
+    fn simulates_alu() {
+        let project = Project::new();
+        let mut alu = Simulator::new(project.chip("ALU").unwrap(), &project).unwrap();
+        // x + y
+        alu.set("x", 1234).unwrap();
+        alu.set("y", 4321).unwrap();
+        alu.set("f", 1).unwrap();
+        alu.eval();
+        assert_eq!(alu.get("out").unwrap(), 5555);
+        assert_eq!(alu.get("zr").unwrap(), 0);
+        // x - y = !(!x + y)
+        alu.set("nx", 1).unwrap();
+        alu.set("no", 1).unwrap();
+        alu.eval();
+        assert_eq!(
+            alu.get("out").unwrap(),
+            (1234u64.wrapping_sub(4321)) & 0xffff
+        );
+        assert_eq!(alu.get("ng").unwrap(), 1);
+        assert_eq!(alu.get("out[15]").unwrap(), 1);
+        assert_eq!(alu.get("ALU/Add16/a").unwrap(), !1234 & 0xffff);
+        assert_eq!(
+            alu.get("xplusy").unwrap(),
+            alu.get("ALU/Add16/out").unwrap()
+        );
+    }
+
+    #[test]
+    fn honors_constants_and_slices() {
+        let mut project = Project::new();
+        let chip = project
+            .add_hdl(
+                "CHIP Swap {
+                    IN in[4];
+                    OUT out[4], one;
+                    PARTS:
+                    Or(a=in[0], b=false, out=out[3]);
+                    Or(a=in[3], b=false, out=out[0]);
+                    Not16(in[0..1]=in[1..2], out[0..1]=out[1..2]);
+                    Or(a=true, b=false, out=one);
+                }",
+            )
+            .unwrap()
+            .clone();
+        let mut swap = Simulator::new(&chip, &project).unwrap();
+        swap.set("in", 0b1001).unwrap();
+        swap.eval();
+        assert_eq!(swap.get("out").unwrap(), 0b1111);
+        assert_eq!(swap.get("one").unwrap(), 1);
+        swap.set("in[3]", 0).unwrap();
+        swap.eval();
+        assert_eq!(swap.get("out").unwrap(), 0b1110);
+    }
+
+    #[test]
+    fn clocks_registers() {
+        let project = Project::new();
+        let mut register = Simulator::new(project.chip("Register").unwrap(), &project).unwrap();
+        register.set("in", 12345).unwrap();
+        register.set("load", 1).unwrap();
+        register.tick();
+        assert_eq!(register.time().to_string(), "0+");
+        assert_eq!(register.get("out").unwrap(), 0);
+        register.tock();
+        assert_eq!(register.time().to_string(), "1");
+        assert_eq!(register.get("out").unwrap(), 12345);
+        register.set("in", 1).unwrap();
+        register.set("load", 0).unwrap();
+        register.step();
+        assert_eq!(register.get("out").unwrap(), 12345);
+    }
+
+    #[test]
+    fn counts_with_pc() {
+        let project = Project::new();
+        let mut pc = Simulator::new(project.chip("PC").unwrap(), &project).unwrap();
+        pc.set("inc", 1).unwrap();
+        for expected in 1..=3 {
+            pc.step();
+            assert_eq!(pc.get("out").unwrap(), expected);
+        }
+        pc.set("in", 0xfffe).unwrap();
+        pc.set("load", 1).unwrap();
+        pc.step();
+        pc.set("load", 0).unwrap();
+        pc.step();
+        assert_eq!(pc.get("out").unwrap(), 0xffff);
+        pc.step();
+        assert_eq!(pc.get("out").unwrap(), 0);
+        pc.set("reset", 1).unwrap();
+        pc.set("load", 1).unwrap();
+        pc.step();
+        assert_eq!(pc.get("out").unwrap(), 0);
+        assert_eq!(pc.time().to_string(), "7");
+    }
+
+    #[test]
+    fn stores_in_ram() {
+        let project = Project::new();
+        let mut ram = Simulator::new(project.chip("RAM64").unwrap(), &project).unwrap();
+        for address in 0..64 {
+            ram.set("address", address).unwrap();
+            ram.set("in", address * 3).unwrap();
+            ram.set("load", 1).unwrap();
+            ram.step();
+        }
+        ram.set("load", 0).unwrap();
+        for address in (0..64).rev() {
+            ram.set("address", address).unwrap();
+            ram.eval();
+            assert_eq!(ram.get("out").unwrap(), address * 3);
+        }
+        assert_eq!(ram.get("RAM64/RAM8[5]/Register[2]/out").unwrap(), 42 * 3);
+    }
+
+    #[test]
+    fn simulates_memory_map() {
+        let project = Project::new();
+        let mut memory = Simulator::new(project.chip("Memory").unwrap(), &project).unwrap();
+        let write = |memory: &mut Simulator, address, value| {
+            memory.set("address", address).unwrap();
+            memory.set("in", value).unwrap();
+            memory.set("load", 1).unwrap();
+            memory.tick();
+            assert_eq!(memory.get("out").unwrap(), 0);
+            memory.tock();
+            memory.set("load", 0).unwrap();
+        };
+        write(&mut memory, 100, 1234);
+        write(&mut memory, 16384 + 5, 0xffff);
+        write(&mut memory, 24576, 7);
+        assert_eq!(memory.memory("RAM16K").unwrap().peek(100), 1234);
+        assert_eq!(memory.memory("Memory/Screen").unwrap().peek(5), 0xffff);
+        assert_eq!(memory.memory("Keyboard").unwrap().peek(0), 0);
+
+        memory.memory_mut("Keyboard").unwrap().poke(0, 75);
+        memory.set("address", 24576).unwrap();
+        memory.eval();
+        assert_eq!(memory.get("out").unwrap(), 75);
+        memory.set("address", 100).unwrap();
+        memory.eval();
+        assert_eq!(memory.get("out").unwrap(), 1234);
+        assert!(matches!(
+            memory.memory("RAM8"),
+            Err(SimulationError::UnknownMemory(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_pins() {
+        let project = Project::new();
+        let chip = parse_hdl("CHIP A { IN a; OUT out; PARTS: Not(in=a, out=out); }").unwrap();
+        let mut simulator = Simulator::new(&chip, &project).unwrap();
+        assert!(matches!(
+            simulator.set("out", 1),
+            Err(SimulationError::NotAnInput(_))
+        ));
+        assert!(matches!(
+            simulator.get("b"),
+            Err(SimulationError::UnknownPin(_))
+        ));
+        assert!(matches!(
+            simulator.get("a[1]"),
+            Err(SimulationError::UnknownPin(_))
+        ));
+    }
+}
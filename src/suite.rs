@@ -0,0 +1,342 @@
+//! Running every test of a project directory
+//!
+//! Each `X.hdl` with an `X.tst` next to it is tested: the script is run against the chip, its output file written
+//! as the official simulator would, and the output compared against the script's `compare-to` file, or `X.cmp` if
+//! it names none.  The results can be reported as plain text, JSON or JUnit XML for continuous integration.
+
+use crate::compare::{compare, Table};
+use crate::limits::Limits;
+use crate::runner::{check_file_name, run_script_with_limits};
+use crate::simulator::SimulationError;
+use crate::test_script::parse_tst;
+use crate::Project;
+use core::fmt;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Outcome of testing one chip
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    /// The output matched the comparison file
+    Passed,
+    /// The output differed from the comparison file
+    Failed,
+    /// The chip's HDL or its test script could not be parsed
+    ParseError,
+    /// The test could not be run, for instance because a part is missing or a file cannot be read
+    Error,
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Status::Passed => "passed",
+            Status::Failed => "failed",
+            Status::ParseError => "parse error",
+            Status::Error => "error",
+        })
+    }
+}
+
+/// The test of one chip
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ChipResult {
+    /// Holds the name of the chip, the stem of its files
+    pub chip: String,
+    /// Holds the outcome
+    pub status: Status,
+    /// Holds what went wrong, unless the test passed
+    pub message: Option<String>,
+}
+
+/// The tests of every chip of a project
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SuiteReport {
+    /// Holds the name of the project directory
+    pub project: String,
+    /// Holds one result per tested chip, ordered by name
+    pub results: Vec<ChipResult>,
+}
+
+impl SuiteReport {
+    /// Number of chips with the given outcome
+    pub fn count(&self, status: Status) -> usize {
+        self.results
+            .iter()
+            .filter(|result| result.status == status)
+            .count()
+    }
+
+    /// Whether every test passed
+    pub fn passed(&self) -> bool {
+        self.count(Status::Passed) == self.results.len()
+    }
+
+    /// The report as JSON
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+
+    /// The report as a JUnit XML test suite, one test case per chip
+    ///
+    /// Failed comparisons are reported as failures, and parse and other errors as errors.
+    pub fn to_junit(&self) -> String {
+        let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+        xml.push_str(&format!(
+            "<testsuite name=\"{}\" tests=\"{}\" failures=\"{}\" errors=\"{}\">\n",
+            escape(&self.project),
+            self.results.len(),
+            self.count(Status::Failed),
+            self.count(Status::ParseError) + self.count(Status::Error)
+        ));
+        for result in &self.results {
+            let case = format!(
+                "  <testcase classname=\"{}\" name=\"{}\"",
+                escape(&self.project),
+                escape(&result.chip)
+            );
+            let message = escape(result.message.as_deref().unwrap_or_default());
+            match result.status {
+                Status::Passed => xml.push_str(&format!("{}/>\n", case)),
+                Status::Failed => xml.push_str(&format!(
+                    "{}>\n    <failure message=\"{}\"/>\n  </testcase>\n",
+                    case, message
+                )),
+                Status::ParseError | Status::Error => xml.push_str(&format!(
+                    "{}>\n    <error type=\"{}\" message=\"{}\"/>\n  </testcase>\n",
+                    case, result.status, message
+                )),
+            }
+        }
+        xml.push_str("</testsuite>\n");
+        xml
+    }
+}
+
+impl fmt::Display for SuiteReport {
+    /// One line per chip followed by the totals
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for result in &self.results {
+            match &result.message {
+                Some(message) => writeln!(f, "{}: {}: {}", result.chip, result.status, message)?,
+                None => writeln!(f, "{}: {}", result.chip, result.status)?,
+            }
+        }
+        writeln!(
+            f,
+            "{} chips: {} passed, {} failed, {} parse errors, {} errors",
+            self.results.len(),
+            self.count(Status::Passed),
+            self.count(Status::Failed),
+            self.count(Status::ParseError),
+            self.count(Status::Error)
+        )
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\n', "&#10;")
+}
+
+/// Run the test of every chip in a project directory that has a test script
+///
+/// Parts are resolved against the chips of the directory and then the builtin chips.  Fails only if the directory
+/// cannot be read; problems with a single chip are reported in its result.
+pub fn run_project_tests<P: AsRef<Path>>(directory: P) -> Result<SuiteReport, SimulationError> {
+    run_project_tests_with_limits(directory, &Limits::default())
+}
+
+/// Run the tests of a project directory like [run_project_tests], failing a chip with an error once its test goes
+/// beyond `limits`
+///
+/// Limits apply to each chip's test separately, so a test that would never finish does not hold up the others.
+pub fn run_project_tests_with_limits<P: AsRef<Path>>(
+    directory: P,
+    limits: &Limits,
+) -> Result<SuiteReport, SimulationError> {
+    let directory = directory.as_ref();
+    let unreadable = |e: std::io::Error| {
+        SimulationError::Io(format!("cannot read {}: {}", directory.display(), e))
+    };
+    let project = Project::from_dir(directory).map_err(unreadable)?;
+    let mut entries = fs::read_dir(directory)
+        .map_err(unreadable)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<PathBuf>, _>>()
+        .map_err(unreadable)?;
+    entries.sort();
+
+    let mut results = vec![];
+    for hdl in entries {
+        let tst = hdl.with_extension("tst");
+        if hdl.extension() != Some("hdl".as_ref()) || !tst.is_file() {
+            continue;
+        }
+        let chip = hdl
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (status, message) = match test_chip(&project, directory, &chip, &tst, limits) {
+            Ok(None) => (Status::Passed, None),
+            Ok(Some(mismatch)) => (Status::Failed, Some(mismatch)),
+            Err((status, message)) => (status, Some(message)),
+        };
+        results.push(ChipResult {
+            chip,
+            status,
+            message,
+        });
+    }
+    Ok(SuiteReport {
+        project: directory.file_name().map_or_else(
+            || directory.display().to_string(),
+            |name| name.to_string_lossy().to_string(),
+        ),
+        results,
+    })
+}
+
+/// Run the test of one chip, returning the first mismatch or why the test could not be run
+fn test_chip(
+    project: &Project,
+    directory: &Path,
+    name: &str,
+    tst: &Path,
+    limits: &Limits,
+) -> Result<Option<String>, (Status, String)> {
+    let error = |e: SimulationError| (Status::Error, e.to_string());
+    let read = |path: &Path| {
+        fs::read_to_string(path).map_err(|e| {
+            (
+                Status::Error,
+                format!("cannot read {}: {}", path.display(), e),
+            )
+        })
+    };
+    // the project falls back to builtin chips, so check the file itself defines the chip
+    let chip = crate::parse_hdl(&read(&directory.join(format!("{}.hdl", name)))?)
+        .map_err(|e| (Status::ParseError, format!("{}.hdl: {}", name, e)))?;
+    if chip.name != name {
+        return Err((
+            Status::Error,
+            format!("{}.hdl defines {} instead of {}", name, chip.name, name),
+        ));
+    }
+    let script =
+        parse_tst(&read(tst)?).map_err(|e| (Status::ParseError, format!("{}.tst: {}", name, e)))?;
+
+    let run =
+        run_script_with_limits(&script, &chip, project, Some(directory), limits).map_err(error)?;
+    if let Some(file) = &run.output_file {
+        check_file_name(file).map_err(error)?;
+        let out = directory.join(file);
+        fs::write(&out, &run.output).map_err(|e| {
+            (
+                Status::Error,
+                format!("cannot write {}: {}", out.display(), e),
+            )
+        })?;
+    }
+    let cmp = run.compare_to.unwrap_or_else(|| format!("{}.cmp", name));
+    check_file_name(&cmp).map_err(error)?;
+    let cmp = directory.join(cmp);
+    let table = |text: &str, path: &Path| {
+        Table::parse(text).map_err(|e| (Status::Error, format!("{}: {}", path.display(), e)))
+    };
+    let expected = table(&read(&cmp)?, &cmp)?;
+    let output = table(&run.output, Path::new("output"))?;
+    Ok(compare(&output, &expected).map(|mismatch| mismatch.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::suite::{run_project_tests, Status};
+    use crate::testing::TempProject;
+
+    const NOT: &str = "CHIP Not { IN in; OUT out; PARTS: Nand(a=in, b=in, out=out); }";
+
+    #[test]
+    fn reports_project_tests() {
+        let project = TempProject::new(
+            "suite",
+            &["And", "Xor", "Or"],
+            &[
+                (
+                    "And",
+                    "CHIP And { IN a, b; OUT out; PARTS: Nand(a=a, b=b, out=n); Not(in=n, out=out); }",
+                ),
+                // an Xor that is really an Or
+                (
+                    "Xor",
+                    "CHIP Xor { IN a, b; OUT out; PARTS: Or(a=a, b=b, out=out); }",
+                ),
+                ("Or", "CHIP Or { IN a, b OUT out; }"),
+                ("Not", NOT),
+            ],
+        );
+
+        let report = run_project_tests(project.path()).unwrap();
+        let statuses: Vec<(&str, Status)> = report
+            .results
+            .iter()
+            .map(|result| (result.chip.as_str(), result.status))
+            .collect();
+        assert_eq!(
+            statuses,
+            vec![
+                ("And", Status::Passed),
+                ("Or", Status::ParseError),
+                ("Xor", Status::Failed)
+            ]
+        );
+        assert!(!report.passed());
+        assert!(project.path().join("And.out").is_file());
+
+        let text = report.to_string();
+        assert!(text.starts_with("And: passed\nOr: parse error: Or.hdl: "));
+        assert!(text.contains(
+            "Xor: failed: comparison failure at line 5, column 18 (out): expected \"   0   \", found \"   1   \"\n"
+        ));
+        assert!(text.ends_with("3 chips: 1 passed, 1 failed, 1 parse errors, 0 errors\n"));
+
+        let json: serde_json::Value = serde_json::from_str(&report.to_json()).unwrap();
+        assert_eq!(json["results"][2]["status"], "failed");
+        assert_eq!(json["results"][0]["message"], serde_json::Value::Null);
+
+        let junit = report.to_junit();
+        assert!(junit.contains("tests=\"3\" failures=\"1\" errors=\"1\""));
+        assert!(junit.contains("  <testcase classname=\"suite-"));
+        assert!(junit.contains("<failure message=\"comparison failure at line 5, column 18 (out): expected &quot;   0   &quot;"));
+        assert!(junit.contains("<error type=\"parse error\" message=\"Or.hdl: "));
+    }
+
+    #[test]
+    fn keeps_files_in_the_project() {
+        let project = TempProject::new("suite-escape", &[], &[("Not", NOT)]);
+        let escape = format!("suite-escape-{}.out", std::process::id());
+        project.write(
+            "Not.tst",
+            &format!(
+                "output-file ../{}, output-list out; set in 0, eval, output;",
+                escape
+            ),
+        );
+
+        let report = run_project_tests(project.path()).unwrap();
+        assert_eq!(report.results[0].status, Status::Error);
+        assert_eq!(
+            report.results[0].message,
+            Some(format!(
+                "invalid script: ../{} is not a file in the script's directory",
+                escape
+            ))
+        );
+        assert!(!std::env::temp_dir().join(escape).exists());
+    }
+}
@@ -0,0 +1,749 @@
+//! Parser for nand2tetris test scripts (`.tst` files)
+//!
+//! Test scripts are sequences of commands separated by commas and ended by `;` (or `!`, a breakpoint in the
+//! official simulator), with `repeat` and `while` blocks around further commands:
+//!
+//! ```text
+//! load Add16.hdl, output-file Add16.out, compare-to Add16.cmp,
+//! output-list a%B1.16.1 b%B1.16.1 out%B1.16.1;
+//! set a %B0000000000000000, set b %XFFFF, eval, output;
+//! repeat 3 { tick, tock, output; }
+//! ```
+//!
+//! Every command in the resulting [TestScript] carries the [Span] of source text it was parsed from.
+
+use crate::format::{Radix, Specifier};
+use crate::keyboard::key_code;
+use core::fmt;
+use nom::branch::alt;
+use nom::bytes::complete::{tag, take_until, take_while, take_while1};
+use nom::character::complete::{digit1, multispace1};
+use nom::combinator::{cut, map, not, opt, recognize};
+use nom::error::{context, VerboseError, VerboseErrorKind};
+use nom::multi::{many0, many1};
+use nom::sequence::{pair, preceded};
+use nom::{Err, IResult};
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+
+type Parsed<'a, T> = IResult<&'a str, T, VerboseError<&'a str>>;
+
+/// A range of source text, as byte offsets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct Span {
+    /// Holds the offset of the first byte
+    pub start: usize,
+    /// Holds the offset just past the last byte
+    pub end: usize,
+}
+
+impl Span {
+    /// Line and column, both counted from one, at which the span starts in its source
+    pub fn line_column(&self, source: &str) -> (usize, usize) {
+        line_column(source, self.start)
+    }
+}
+
+/// Selection within a variable written in brackets
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Index {
+    /// `[]`, the whole value of a builtin chip's state such as `DRegister[]`
+    Whole,
+    /// `[3]`, a single bit of a bus or a single word of a memory
+    At(usize),
+    /// `[0..7]`, a range of bits
+    Range(usize, usize),
+}
+
+/// A pin, internal variable or builtin state named by a command
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Variable {
+    /// Holds the name, such as `a`, `time` or `RAM16K`
+    pub name: String,
+    /// Holds the selection in brackets, if any
+    pub index: Option<Index>,
+}
+
+impl fmt::Display for Variable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.index {
+            None => write!(f, "{}", self.name),
+            Some(Index::Whole) => write!(f, "{}[]", self.name),
+            Some(Index::At(index)) => write!(f, "{}[{}]", self.name, index),
+            Some(Index::Range(start, end)) => write!(f, "{}[{}..{}]", self.name, start, end),
+        }
+    }
+}
+
+/// One column of an `output-list` command
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct OutputColumn {
+    /// Holds the variable printed in the column
+    pub variable: Variable,
+    /// Holds how the variable is printed, if the script says
+    pub format: Option<Specifier>,
+    /// Holds the source text of the column
+    pub span: Span,
+}
+
+/// Comparison made by a `while` condition
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Comparison {
+    /// `=`
+    Equal,
+    /// `<>`
+    NotEqual,
+    /// `<`
+    Less,
+    /// `>`
+    Greater,
+    /// `<=`
+    LessEqual,
+    /// `>=`
+    GreaterEqual,
+}
+
+/// One side of a `while` condition
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Operand {
+    /// The current value of a variable
+    Variable(Variable),
+    /// A constant
+    Constant(i64),
+}
+
+/// The condition of a `while` block, such as `RAM[0] <> 0`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Condition {
+    /// Holds the left hand side
+    pub left: Operand,
+    /// Holds the comparison
+    pub comparison: Comparison,
+    /// Holds the right hand side
+    pub right: Operand,
+}
+
+/// What a command does
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CommandKind {
+    /// `load Xor.hdl`, load the chip to test, or the chip named after the script if no file is given
+    Load(Option<String>),
+    /// `ROM32K load Max.hack`, load a file into a memory of the chip
+    LoadMemory {
+        /// Holds the memory to load
+        memory: String,
+        /// Holds the file to load it from
+        file: String,
+    },
+    /// `output-file Xor.out`, where to write outputs
+    OutputFile(String),
+    /// `compare-to Xor.cmp`, the file outputs must match
+    CompareTo(String),
+    /// `output-list a%B3.1.3 out%B3.1.3`, the columns each output line prints
+    OutputList(Vec<OutputColumn>),
+    /// `set a %B1010`, give a variable a value
+    Set {
+        /// Holds the variable to set
+        variable: Variable,
+        /// Holds the value, already converted from its written radix
+        value: i64,
+    },
+    /// `eval`, evaluate the chip
+    Eval,
+    /// `tick`, the rising clock edge
+    Tick,
+    /// `tock`, the falling clock edge
+    Tock,
+    /// `output`, print a line of the output list
+    Output,
+    /// `echo "text"`, show a message
+    Echo(String),
+    /// `clear-echo`, clear the message
+    ClearEcho,
+    /// `key A`, hold a key down on the `Keyboard` builtin, given by its code, or `key release` for zero
+    Key(u16),
+    /// `repeat 10 { ... }`, run commands a number of times, or forever without a count
+    Repeat {
+        /// Holds the number of repetitions, if any
+        count: Option<usize>,
+        /// Holds the repeated commands
+        body: Vec<Command>,
+    },
+    /// `while out <> 0 { ... }`, run commands for as long as a condition holds
+    While {
+        /// Holds the condition checked before each run of the body
+        condition: Condition,
+        /// Holds the repeated commands
+        body: Vec<Command>,
+    },
+}
+
+/// A command and where it was written
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Command {
+    /// Holds what the command does
+    pub kind: CommandKind,
+    /// Holds the source text of the command, including the body of blocks
+    pub span: Span,
+}
+
+/// A type that represents a parsed test script
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+pub struct TestScript {
+    /// Holds the top level commands in order
+    pub commands: Vec<Command>,
+}
+
+/// Error returned when a test script cannot be parsed
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TestScriptError {
+    /// Holds what was expected where parsing stopped
+    pub message: String,
+    /// Holds the byte offset at which parsing stopped
+    pub offset: usize,
+    /// Holds the line, counted from one, at which parsing stopped
+    pub line: usize,
+    /// Holds the column, counted from one, at which parsing stopped
+    pub column: usize,
+}
+
+impl fmt::Display for TestScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for TestScriptError {}
+
+/// parse_tst will consume a test script and return its commands, or where and why parsing failed
+pub fn parse_tst(text: &str) -> Result<TestScript, TestScriptError> {
+    let parsed = preceded(separator, |rest| statements(text, rest))(text);
+    let error = match parsed {
+        Ok(("", commands)) => return Ok(TestScript { commands }),
+        // explain why the next statement does not parse
+        Ok((rest, _)) => match statement(text, rest) {
+            Err(Err::Error(e)) | Err(Err::Failure(e)) => e,
+            _ => VerboseError {
+                errors: vec![(rest, VerboseErrorKind::Context("a command"))],
+            },
+        },
+        Err(Err::Error(e)) | Err(Err::Failure(e)) => e,
+        Err(Err::Incomplete(_)) => VerboseError {
+            errors: vec![("", VerboseErrorKind::Context("more input"))],
+        },
+    };
+    // the innermost error says where parsing stopped and the innermost context what was expected there
+    let rest = error.errors.first().map_or("", |&(rest, _)| rest);
+    let message = error
+        .errors
+        .iter()
+        .find_map(|(_, kind)| match kind {
+            VerboseErrorKind::Context(context) => Some(format!("expected {}", context)),
+            _ => None,
+        })
+        .unwrap_or_else(|| "unexpected input".to_string());
+    let offset = text.len() - rest.len();
+    let (line, column) = line_column(text, offset);
+    Err(TestScriptError {
+        message,
+        offset,
+        line,
+        column,
+    })
+}
+
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before.chars().rev().take_while(|&c| c != '\n').count() + 1;
+    (line, column)
+}
+
+fn span(source: &str, start: &str, end: &str) -> Span {
+    Span {
+        start: source.len() - start.len(),
+        end: source.len() - end.len(),
+    }
+}
+
+/// Whitespace and comments, which may be written between any two tokens
+fn separator(text: &str) -> Parsed<'_, ()> {
+    fn comment_line(text: &str) -> Parsed<'_, ()> {
+        let (text, _) = tag("//")(text)?;
+        let (text, _) = take_while(|c| c != '\n')(text)?;
+        Ok((text, ()))
+    }
+    fn comment_block(text: &str) -> Parsed<'_, ()> {
+        let (text, _) = tag("/*")(text)?;
+        let (text, _) = take_until("*/")(text)?;
+        let (text, _) = tag("*/")(text)?;
+        Ok((text, ()))
+    }
+    let (text, _) = many0(alt((map(multispace1, |_| ()), comment_line, comment_block)))(text)?;
+    Ok((text, ()))
+}
+
+fn is_word(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+/// A keyword that is not merely the start of a longer word
+fn keyword<'a>(word: &'static str) -> impl Fn(&'a str) -> Parsed<'a, &'a str> {
+    move |text| {
+        let (text, matched) = tag(word)(text)?;
+        let (text, _) = not(take_while1(is_word))(text)?;
+        Ok((text, matched))
+    }
+}
+
+fn identifier(text: &str) -> Parsed<'_, &str> {
+    context(
+        "a name",
+        take_while1(|c: char| c.is_ascii_alphanumeric() || c == '_'),
+    )(text)
+}
+
+fn number(text: &str) -> Parsed<'_, usize> {
+    let (rest, digits) = context("a number", digit1)(text)?;
+    match digits.parse() {
+        Ok(number) => Ok((rest, number)),
+        Err(_) => Err(Err::Failure(VerboseError {
+            errors: vec![(text, VerboseErrorKind::Context("a smaller number"))],
+        })),
+    }
+}
+
+fn file_name(text: &str) -> Parsed<'_, String> {
+    let (text, name) = context(
+        "a file name",
+        take_while1(|c: char| !c.is_whitespace() && !matches!(c, ',' | ';' | '!')),
+    )(text)?;
+    Ok((text, name.to_string()))
+}
+
+/// A key as written for [key_code], or `release`
+fn key(text: &str) -> Parsed<'_, CommandKind> {
+    let (rest, name) = file_name(text)?;
+    let code = match name.as_str() {
+        "release" => Some(0),
+        name => key_code(name),
+    };
+    match code {
+        Some(code) => Ok((rest, CommandKind::Key(code))),
+        None => Err(Err::Failure(VerboseError {
+            errors: vec![(text, VerboseErrorKind::Context("a key or release"))],
+        })),
+    }
+}
+
+fn variable(text: &str) -> Parsed<'_, Variable> {
+    fn index(text: &str) -> Parsed<'_, Index> {
+        let (text, _) = tag("[")(text)?;
+        let (text, _) = separator(text)?;
+        if let Ok((text, _)) = tag::<_, _, VerboseError<&str>>("]")(text) {
+            return Ok((text, Index::Whole));
+        }
+        let (text, start) = cut(number)(text)?;
+        let (text, _) = separator(text)?;
+        let (text, end) = opt(preceded(pair(tag(".."), separator), cut(number)))(text)?;
+        let (text, _) = separator(text)?;
+        let (text, _) = cut(context("\"]\"", tag("]")))(text)?;
+        Ok((
+            text,
+            match end {
+                Some(end) => Index::Range(start, end),
+                None => Index::At(start),
+            },
+        ))
+    }
+    let (text, name) = identifier(text)?;
+    let (text, index) = opt(index)(text)?;
+    Ok((
+        text,
+        Variable {
+            name: name.to_string(),
+            index,
+        },
+    ))
+}
+
+/// A constant in decimal, or in binary, hexadecimal or decimal after `%B`, `%X` or `%D`
+fn value(text: &str) -> Parsed<'_, i64> {
+    fn radix<'a>(prefix: &'static str, radix: u32) -> impl Fn(&'a str) -> Parsed<'a, i64> {
+        move |text| {
+            let (text, _) = tag(prefix)(text)?;
+            let digits = recognize(pair(
+                opt(tag("-")),
+                take_while1(|c: char| c.is_digit(radix)),
+            ));
+            // digits must follow a radix prefix, but without one this may not be a value at all
+            let (rest, digits) = if prefix.is_empty() {
+                digits(text)?
+            } else {
+                cut(context("digits", digits))(text)?
+            };
+            match i64::from_str_radix(digits, radix) {
+                Ok(value) => Ok((rest, value)),
+                Err(_) => Err(Err::Failure(VerboseError {
+                    errors: vec![(text, VerboseErrorKind::Context("a smaller value"))],
+                })),
+            }
+        }
+    }
+    let (text, value) = context(
+        "a value",
+        alt((
+            radix("%B", 2),
+            radix("%X", 16),
+            radix("%D", 10),
+            radix("", 10),
+        )),
+    )(text)?;
+    let (text, _) = not(take_while1(is_word))(text)?;
+    Ok((text, value))
+}
+
+fn output_column<'a>(source: &'a str, text: &'a str) -> Parsed<'a, OutputColumn> {
+    fn specifier(text: &str) -> Parsed<'_, Specifier> {
+        let (text, _) = tag("%")(text)?;
+        let (rest, letter) = cut(context("B, D, X or S", take_while1(|c| c != '.')))(text)?;
+        let radix = match letter.chars().next().and_then(Radix::from_letter) {
+            Some(radix) if letter.len() > 1 => radix,
+            _ => {
+                return Err(Err::Failure(VerboseError {
+                    errors: vec![(text, VerboseErrorKind::Context("B, D, X or S"))],
+                }))
+            }
+        };
+        // the letter is followed directly by the left padding
+        let (_, left) = cut(number)(&letter[1..])?;
+        let (rest, _) = cut(context("\".\"", tag(".")))(rest)?;
+        let (rest, len) = cut(number)(rest)?;
+        let (rest, _) = cut(context("\".\"", tag(".")))(rest)?;
+        let (rest, right) = cut(number)(rest)?;
+        Ok((
+            rest,
+            Specifier {
+                radix,
+                left,
+                len,
+                right,
+            },
+        ))
+    }
+    let start = text;
+    let (text, variable) = variable(text)?;
+    let (text, format) = opt(specifier)(text)?;
+    Ok((
+        text,
+        OutputColumn {
+            variable,
+            format,
+            span: span(source, start, text),
+        },
+    ))
+}
+
+fn condition(text: &str) -> Parsed<'_, Condition> {
+    fn operand(text: &str) -> Parsed<'_, Operand> {
+        alt((
+            map(value, Operand::Constant),
+            map(variable, Operand::Variable),
+        ))(text)
+    }
+    let (text, left) = context("a condition", operand)(text)?;
+    let (text, _) = separator(text)?;
+    let (text, comparison) = cut(context(
+        "a comparison",
+        alt((
+            map(tag("<>"), |_| Comparison::NotEqual),
+            map(tag("<="), |_| Comparison::LessEqual),
+            map(tag(">="), |_| Comparison::GreaterEqual),
+            map(tag("<"), |_| Comparison::Less),
+            map(tag(">"), |_| Comparison::Greater),
+            map(tag("="), |_| Comparison::Equal),
+        )),
+    ))(text)?;
+    let (text, _) = separator(text)?;
+    let (text, right) = cut(context("a value or variable", operand))(text)?;
+    Ok((
+        text,
+        Condition {
+            left,
+            comparison,
+            right,
+        },
+    ))
+}
+
+/// A single command, not including the comma or semicolon after it
+fn command<'a>(source: &'a str, text: &'a str) -> Parsed<'a, Command> {
+    let start = text;
+    let argument = |parser: fn(&'a str) -> Parsed<'a, String>| {
+        move |text| preceded(separator, cut(parser))(text)
+    };
+    let (text, kind) = alt((
+        map(
+            preceded(keyword("output-file"), argument(file_name)),
+            CommandKind::OutputFile,
+        ),
+        map(
+            preceded(keyword("compare-to"), argument(file_name)),
+            CommandKind::CompareTo,
+        ),
+        map(
+            preceded(
+                keyword("output-list"),
+                cut(context(
+                    "output columns",
+                    many1(preceded(separator, |text| output_column(source, text))),
+                )),
+            ),
+            CommandKind::OutputList,
+        ),
+        map(keyword("output"), |_| CommandKind::Output),
+        map(
+            preceded(keyword("load"), opt(preceded(separator, file_name))),
+            CommandKind::Load,
+        ),
+        |text| {
+            let (text, _) = keyword("set")(text)?;
+            let (text, variable) = preceded(separator, cut(variable))(text)?;
+            let (text, value) = preceded(separator, cut(value))(text)?;
+            Ok((text, CommandKind::Set { variable, value }))
+        },
+        map(keyword("eval"), |_| CommandKind::Eval),
+        map(keyword("tick"), |_| CommandKind::Tick),
+        map(keyword("tock"), |_| CommandKind::Tock),
+        |text| {
+            let (text, _) = keyword("echo")(text)?;
+            let (text, _) = separator(text)?;
+            let (text, _) = cut(context("a quoted string", tag("\"")))(text)?;
+            let (text, message) = take_while(|c| c != '"')(text)?;
+            let (text, _) = cut(context("a closing quote", tag("\"")))(text)?;
+            Ok((text, CommandKind::Echo(message.to_string())))
+        },
+        map(keyword("clear-echo"), |_| CommandKind::ClearEcho),
+        preceded(keyword("key"), preceded(separator, cut(key))),
+        |text| {
+            let (text, memory) = identifier(text)?;
+            let (text, _) = separator(text)?;
+            let (text, _) = keyword("load")(text)?;
+            let (text, file) = preceded(separator, cut(file_name))(text)?;
+            Ok((
+                text,
+                CommandKind::LoadMemory {
+                    memory: memory.to_string(),
+                    file,
+                },
+            ))
+        },
+    ))(text)?;
+    Ok((
+        text,
+        Command {
+            kind,
+            span: span(source, start, text),
+        },
+    ))
+}
+
+/// A `repeat` or `while` block
+fn block<'a>(source: &'a str, text: &'a str) -> Parsed<'a, Command> {
+    let start = text;
+    let (text, header) = alt((
+        map(
+            preceded(keyword("repeat"), opt(preceded(separator, number))),
+            |count| (Some(count), None),
+        ),
+        map(
+            preceded(keyword("while"), preceded(separator, cut(condition))),
+            |condition| (None, Some(condition)),
+        ),
+    ))(text)?;
+    let (text, _) = separator(text)?;
+    let (text, _) = cut(context("\"{\"", tag("{")))(text)?;
+    let (text, _) = separator(text)?;
+    let (text, body) = statements(source, text)?;
+    let (text, _) = cut(context("a command or \"}\"", tag("}")))(text)?;
+    let kind = match header {
+        (Some(count), _) => CommandKind::Repeat { count, body },
+        (None, Some(condition)) => CommandKind::While { condition, body },
+        (None, None) => unreachable!("a block is either repeat or while"),
+    };
+    Ok((
+        text,
+        Command {
+            kind,
+            span: span(source, start, text),
+        },
+    ))
+}
+
+/// A block, or commands separated by commas and ended by a semicolon or exclamation mark
+fn statement<'a>(source: &'a str, text: &'a str) -> Parsed<'a, Vec<Command>> {
+    match block(source, text) {
+        Ok((text, block)) => return Ok((text, vec![block])),
+        Err(Err::Error(_)) => {}
+        Err(failure) => return Err(failure),
+    }
+    let (mut text, first) = context("a command", |text| command(source, text))(text)?;
+    let mut commands = vec![first];
+    loop {
+        let (rest, _) = separator(text)?;
+        let (rest, end) = cut(context(
+            "\",\" or \";\"",
+            alt((tag(","), tag(";"), tag("!"))),
+        ))(rest)?;
+        if end != "," {
+            return Ok((rest, commands));
+        }
+        let (rest, _) = separator(rest)?;
+        let (rest, next) = cut(context("a command", |text| command(source, text)))(rest)?;
+        commands.push(next);
+        text = rest;
+    }
+}
+
+fn statements<'a>(source: &'a str, text: &'a str) -> Parsed<'a, Vec<Command>> {
+    let (text, statements) = many0(|text| {
+        let (text, commands) = statement(source, text)?;
+        let (text, _) = separator(text)?;
+        Ok((text, commands))
+    })(text)?;
+    Ok((text, statements.into_iter().flatten().collect()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::{Radix, Specifier};
+    use crate::test_script::{parse_tst, CommandKind, Comparison, Index, Operand, Span, Variable};
+
+    #[test]
+    fn parses_commands() {
+        let source = "// Add16 test\n\
+            load Add16.hdl,\n\
+            output-file Add16.out,\n\
+            compare-to Add16.cmp,\n\
+            output-list a%B1.16.1 b%X2.4.2 out%D1.6.1 time%S1.4.1 RAM16K[0]%D1.7.1;\n\
+            /* a block\n comment */\n\
+            set a %B1111, set b %XfF, set RAM16K[3] -1, set c 12,\n\
+            eval, output;\n\
+            ROM32K load Max.hack;\n\
+            tick, tock!\n\
+            echo \"Hello, world\", clear-echo;\n\
+            key left, key release;";
+        let script = parse_tst(source).unwrap();
+        let kinds: Vec<&CommandKind> = script
+            .commands
+            .iter()
+            .map(|command| &command.kind)
+            .collect();
+        assert_eq!(kinds.len(), 17);
+        assert_eq!(kinds[0], &CommandKind::Load(Some("Add16.hdl".to_string())));
+        assert_eq!(kinds[2], &CommandKind::CompareTo("Add16.cmp".to_string()));
+        match kinds[3] {
+            CommandKind::OutputList(columns) => {
+                assert_eq!(columns.len(), 5);
+                assert_eq!(
+                    columns[1].format,
+                    Some(Specifier {
+                        radix: Radix::Hex,
+                        left: 2,
+                        len: 4,
+                        right: 2
+                    })
+                );
+                assert_eq!(columns[4].variable.to_string(), "RAM16K[0]");
+                assert_eq!(
+                    &source[columns[2].span.start..columns[2].span.end],
+                    "out%D1.6.1"
+                );
+            }
+            other => panic!("expected an output list, found {:?}", other),
+        }
+        assert_eq!(
+            kinds[5],
+            &CommandKind::Set {
+                variable: Variable {
+                    name: "b".to_string(),
+                    index: None
+                },
+                value: 255
+            }
+        );
+        assert_eq!(
+            kinds[6],
+            &CommandKind::Set {
+                variable: Variable {
+                    name: "RAM16K".to_string(),
+                    index: Some(Index::At(3))
+                },
+                value: -1
+            }
+        );
+        assert_eq!(kinds[9], &CommandKind::Output);
+        assert_eq!(
+            kinds[10],
+            &CommandKind::LoadMemory {
+                memory: "ROM32K".to_string(),
+                file: "Max.hack".to_string()
+            }
+        );
+        assert_eq!(kinds[13], &CommandKind::Echo("Hello, world".to_string()));
+        assert_eq!(kinds[14], &CommandKind::ClearEcho);
+        assert_eq!(kinds[15], &CommandKind::Key(130));
+        assert_eq!(kinds[16], &CommandKind::Key(0));
+        assert_eq!(script.commands[8].span.line_column(source), (9, 1));
+    }
+
+    #[test]
+    fn parses_blocks() {
+        let source = "repeat 2 {\n  tick, tock; output;\n}\nwhile RAM[0] <> 0 { repeat { eval; } }";
+        let script = parse_tst(source).unwrap();
+        assert_eq!(script.commands.len(), 2);
+        match &script.commands[0].kind {
+            CommandKind::Repeat { count, body } => {
+                assert_eq!(*count, Some(2));
+                assert_eq!(body.len(), 3);
+            }
+            other => panic!("expected repeat, found {:?}", other),
+        }
+        assert_eq!(script.commands[0].span, Span { start: 0, end: 34 });
+        match &script.commands[1].kind {
+            CommandKind::While { condition, body } => {
+                assert_eq!(condition.comparison, Comparison::NotEqual);
+                assert_eq!(condition.right, Operand::Constant(0));
+                assert!(matches!(
+                    &body[0].kind,
+                    CommandKind::Repeat { count: None, .. }
+                ));
+            }
+            other => panic!("expected while, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn reports_errors() {
+        let error = parse_tst("load Not.hdl,\nset in;").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 7));
+        assert_eq!(error.message, "expected a value");
+        assert_eq!(error.to_string(), "line 2, column 7: expected a value");
+
+        let error = parse_tst("eval\noutput;").unwrap_err();
+        assert_eq!((error.line, error.column, error.offset), (2, 1, 5));
+        assert_eq!(error.message, "expected \",\" or \";\"");
+
+        let error = parse_tst("output-list a%Q1.2.1;").unwrap_err();
+        assert_eq!(error.message, "expected B, D, X or S");
+        let error = parse_tst("repeat 3 { eval; ").unwrap_err();
+        assert_eq!(error.message, "expected a command or \"}\"");
+        assert!(parse_tst("frobnicate;").is_err());
+        let error = parse_tst("key tab;").unwrap_err();
+        assert_eq!(error.message, "expected a key or release");
+    }
+}
@@ -0,0 +1,49 @@
+//! Fixtures shared by the tests of several modules
+
+use crate::generate::{generate_test, Vectors};
+use crate::Project;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A project directory in the system's temporary directory, removed again when dropped
+pub(crate) struct TempProject {
+    directory: PathBuf,
+}
+
+impl TempProject {
+    /// Write `X.hdl` for each `(X, hdl)` pair, and an exhaustive `X.tst` and `X.cmp` for each builtin chip `X` of
+    /// `tested`
+    pub(crate) fn new(name: &str, tested: &[&str], chips: &[(&str, &str)]) -> TempProject {
+        let directory = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        let project = TempProject { directory };
+        let builtins = Project::new();
+        for name in tested {
+            let test = generate_test(builtins.chip(name).unwrap(), &builtins, Vectors::Exhaustive)
+                .unwrap();
+            project.write(&format!("{}.tst", name), &test.tst);
+            project.write(&format!("{}.cmp", name), &test.cmp);
+        }
+        for (name, hdl) in chips {
+            project.write(&format!("{}.hdl", name), hdl);
+        }
+        project
+    }
+
+    /// Write a file of the project
+    pub(crate) fn write(&self, file: &str, contents: &str) {
+        fs::write(self.directory.join(file), contents).unwrap();
+    }
+
+    /// The project directory
+    pub(crate) fn path(&self) -> &Path {
+        &self.directory
+    }
+}
+
+impl Drop for TempProject {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.directory);
+    }
+}
@@ -0,0 +1,188 @@
+//! Exhaustive truth tables of combinational chips
+
+use crate::bitsliced::Program;
+use crate::flatten::{flatten, Bus, Netlist};
+use crate::format::{self, Column};
+use crate::simulator::SimulationError;
+use crate::{Chip, Project};
+use serde::{Deserialize, Serialize};
+
+/// Widest combined input, in bits, that a truth table will enumerate
+pub const MAX_INPUT_BITS: usize = 20;
+
+/// A named pin and its width
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Signal {
+    /// Holds the name of the pin
+    pub name: String,
+    /// Holds the width of the pin in bits
+    pub width: usize,
+}
+
+/// A type that represents the truth table of a combinational chip
+///
+/// Rows are ordered like the official comparison files: the first input is the most significant, so the last
+/// input changes fastest.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct TruthTable {
+    /// Holds the name of the chip
+    pub chip: String,
+    /// Holds the input pins of the chip
+    pub inputs: Vec<Signal>,
+    /// Holds the output pins of the chip
+    pub outputs: Vec<Signal>,
+    /// Holds one row per input combination: the input values followed by the output values
+    pub rows: Vec<Vec<u64>>,
+}
+
+impl TruthTable {
+    /// Flatten a chip and enumerate every combination of its inputs
+    pub fn new(chip: &Chip, project: &Project) -> Result<TruthTable, SimulationError> {
+        TruthTable::from_netlist(&flatten(chip, project)?)
+    }
+
+    /// Enumerate every combination of the inputs of a flattened chip
+    ///
+    /// Fails if the chip is not combinational or if its inputs are wider than [MAX_INPUT_BITS] combined.
+    pub fn from_netlist(netlist: &Netlist) -> Result<TruthTable, SimulationError> {
+        let signals = |buses: &[Bus]| -> Vec<Signal> {
+            buses
+                .iter()
+                .map(|bus| Signal {
+                    name: bus.name.clone(),
+                    width: bus.nets.len(),
+                })
+                .collect()
+        };
+        let inputs = signals(&netlist.inputs);
+        let outputs = signals(&netlist.outputs);
+        let bits: usize = inputs.iter().map(|signal| signal.width).sum();
+        if bits > MAX_INPUT_BITS {
+            return Err(SimulationError::TooManyInputs(bits));
+        }
+        let program = Program::compile(netlist)?;
+
+        let vectors: Vec<Vec<u64>> = (0..1u64 << bits)
+            .map(|combination| {
+                let mut shift = bits;
+                inputs
+                    .iter()
+                    .map(|signal| {
+                        shift -= signal.width;
+                        combination >> shift & ((1 << signal.width) - 1)
+                    })
+                    .collect()
+            })
+            .collect();
+        let results = program.run(&vectors)?;
+        Ok(TruthTable {
+            chip: netlist.name.clone(),
+            inputs,
+            outputs,
+            rows: vectors
+                .into_iter()
+                .zip(results)
+                .map(|(mut row, outputs)| {
+                    row.extend(outputs);
+                    row
+                })
+                .collect(),
+        })
+    }
+
+    fn signals(&self) -> impl Iterator<Item = &Signal> {
+        self.inputs.iter().chain(&self.outputs)
+    }
+
+    /// Comma separated values with a header line and decimal values
+    pub fn to_csv(&self) -> String {
+        let mut csv = self
+            .signals()
+            .map(|signal| signal.name.as_str())
+            .collect::<Vec<_>>()
+            .join(",");
+        csv.push('\n');
+        for row in &self.rows {
+            let values: Vec<String> = row.iter().map(u64::to_string).collect();
+            csv.push_str(&values.join(","));
+            csv.push('\n');
+        }
+        csv
+    }
+
+    /// A Markdown table with values in binary, zero padded to the width of each pin
+    pub fn to_markdown(&self) -> String {
+        let names: Vec<&str> = self.signals().map(|signal| signal.name.as_str()).collect();
+        let mut markdown = format!("| {} |\n", names.join(" | "));
+        markdown.push_str(&format!(
+            "|{}\n",
+            names.iter().map(|_| "---|").collect::<String>()
+        ));
+        for row in &self.rows {
+            let values: Vec<String> = self
+                .signals()
+                .zip(row)
+                .map(|(signal, value)| format!("{:0width$b}", value, width = signal.width))
+                .collect();
+            markdown.push_str(&format!("| {} |\n", values.join(" | ")));
+        }
+        markdown
+    }
+
+    /// A comparison file laid out like the ones shipped with the course
+    pub fn to_cmp(&self) -> String {
+        let columns: Vec<Column> = self
+            .signals()
+            .map(|signal| Column::for_pin(&signal.name, signal.width))
+            .collect();
+        let mut cmp = format::header(&columns);
+        cmp.push('\n');
+        for row in &self.rows {
+            cmp.push_str(&format::row(&columns, row));
+            cmp.push('\n');
+        }
+        cmp
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::simulator::SimulationError;
+    use crate::truth_table::TruthTable;
+    use crate::Project;
+
+    #[test]
+    fn tabulates_mux() {
+        let project = Project::new();
+        let table = TruthTable::new(project.chip("Mux").unwrap(), &project).unwrap();
+        assert_eq!(table.rows.len(), 8);
+        assert_eq!(table.rows[1], vec![0, 0, 1, 0]);
+        assert_eq!(table.rows[6], vec![1, 1, 0, 1]);
+        assert_eq!(
+            table.to_cmp().lines().take(3).collect::<Vec<_>>(),
+            vec![
+                "|   a   |   b   |  sel  |  out  |",
+                "|   0   |   0   |   0   |   0   |",
+                "|   0   |   0   |   1   |   0   |",
+            ]
+        );
+        assert!(table
+            .to_csv()
+            .starts_with("a,b,sel,out\n0,0,0,0\n0,0,1,0\n"));
+        assert!(table
+            .to_markdown()
+            .starts_with("| a | b | sel | out |\n|---|---|---|---|\n| 0 | 0 | 0 | 0 |\n"));
+    }
+
+    #[test]
+    fn tabulates_buses() {
+        let project = Project::new();
+        let table = TruthTable::new(project.chip("DMux4Way").unwrap(), &project).unwrap();
+        assert_eq!(table.rows[7], vec![1, 3, 0, 0, 0, 1]);
+        assert!(table.to_markdown().contains("| 1 | 11 | 0 | 0 | 0 | 1 |"));
+        assert!(matches!(
+            TruthTable::new(project.chip("Add16").unwrap(), &project),
+            Err(SimulationError::TooManyInputs(32))
+        ));
+    }
+}
@@ -0,0 +1,272 @@
+//! Recording signals during simulation as Value Change Dump waveforms
+//!
+//! A [Waveform] is started with [Simulator::record](crate::Simulator::record) and samples the chosen signals after
+//! every [eval](crate::Simulator::eval), [tick](crate::Simulator::tick) and [tock](crate::Simulator::tock), one
+//! time unit apart.  A `clock` signal that is high between each tick and the following tock is recorded alongside
+//! them so clock edges line up with the changes they cause.
+
+use crate::flatten::Net;
+use crate::memory;
+use crate::simulator::Time;
+use std::collections::BTreeMap;
+
+/// A recorded signal and the times at which its value changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Trace {
+    name: String,
+    nets: Vec<Net>,
+    changes: Vec<(u64, u64)>,
+}
+
+impl Trace {
+    fn sample(&mut self, time: u64, value: u64) {
+        if self.changes.last().map(|&(_, last)| last) != Some(value) {
+            self.changes.push((time, value));
+        }
+    }
+}
+
+/// A type that represents the recorded history of a set of signals
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Waveform {
+    chip: String,
+    clock: Trace,
+    traces: Vec<Trace>,
+    samples: u64,
+}
+
+impl Waveform {
+    /// Start recording named groups of nets of a chip
+    pub(crate) fn new(chip: &str, signals: Vec<(String, Vec<Net>)>) -> Waveform {
+        Waveform {
+            chip: chip.to_string(),
+            clock: Trace {
+                name: "clock".to_string(),
+                nets: vec![],
+                changes: vec![],
+            },
+            traces: signals
+                .into_iter()
+                .map(|(name, nets)| Trace {
+                    name,
+                    nets,
+                    changes: vec![],
+                })
+                .collect(),
+            samples: 0,
+        }
+    }
+
+    /// Record the current value of every signal
+    pub(crate) fn sample(&mut self, time: Time, values: &[bool]) {
+        let now = self.samples;
+        self.clock.sample(now, time.ticked as u64);
+        for trace in self.traces.iter_mut() {
+            let value = memory::read(&trace.nets, values);
+            trace.sample(now, value);
+        }
+        self.samples += 1;
+    }
+
+    /// Names of the recorded signals, in the order they were requested
+    pub fn signals(&self) -> impl Iterator<Item = &str> {
+        self.traces.iter().map(|trace| trace.name.as_str())
+    }
+
+    /// Number of samples taken so far
+    pub fn len(&self) -> u64 {
+        self.samples
+    }
+
+    /// Whether no samples have been taken
+    pub fn is_empty(&self) -> bool {
+        self.samples == 0
+    }
+
+    /// Values of a recorded signal at each sample, or `None` if the signal was not recorded
+    pub fn values(&self, signal: &str) -> Option<Vec<u64>> {
+        let trace = self.traces.iter().find(|trace| trace.name == signal)?;
+        // every signal changes at the first sample, and at most once per sample
+        let mut values = Vec::with_capacity(self.samples as usize);
+        for (index, &(time, value)) in trace.changes.iter().enumerate() {
+            let until = trace
+                .changes
+                .get(index + 1)
+                .map_or(self.samples, |&(next, _)| next);
+            values.extend((time..until).map(|_| value));
+        }
+        Some(values)
+    }
+
+    /// Render the recording as a Value Change Dump, viewable in tools like GTKWave
+    ///
+    /// Each signal is one variable as wide as its bus, nested in scopes following its hierarchical path.
+    pub fn to_vcd(&self) -> String {
+        let traces: Vec<&Trace> = std::iter::once(&self.clock).chain(&self.traces).collect();
+        let codes: Vec<String> = (0..traces.len()).map(identifier).collect();
+
+        let mut vcd = String::new();
+        vcd.push_str("$version nand2tetris-hdl-parser $end\n");
+        vcd.push_str("$timescale 1ns $end\n");
+        let mut root = Scope::default();
+        for (index, trace) in traces.iter().enumerate() {
+            let path = trace
+                .name
+                .strip_prefix(&format!("{}/", self.chip))
+                .unwrap_or(&trace.name);
+            let mut parts: Vec<&str> = path.split('/').collect();
+            let name = parts.pop().unwrap_or_default();
+            let scope = parts.into_iter().fold(&mut root, |scope, part| {
+                scope.children.entry(part.to_string()).or_default()
+            });
+            scope.vars.push(var(name, width(trace), &codes[index]));
+        }
+        root.write(&self.chip, &mut vcd);
+        vcd.push_str("$enddefinitions $end\n");
+
+        let mut changes: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+        for (trace, code) in traces.iter().zip(&codes) {
+            for &(time, value) in &trace.changes {
+                changes
+                    .entry(time)
+                    .or_default()
+                    .push(change(value, width(trace), code));
+            }
+        }
+        for (time, changes) in changes {
+            vcd.push_str(&format!("#{}\n", time));
+            if time == 0 {
+                vcd.push_str("$dumpvars\n");
+            }
+            for change in changes {
+                vcd.push_str(&change);
+                vcd.push('\n');
+            }
+            if time == 0 {
+                vcd.push_str("$end\n");
+            }
+        }
+        vcd.push_str(&format!("#{}\n", self.samples));
+        vcd
+    }
+}
+
+/// Variables and nested scopes of one level of the chip hierarchy
+#[derive(Debug, Default)]
+struct Scope {
+    vars: Vec<String>,
+    children: BTreeMap<String, Scope>,
+}
+
+impl Scope {
+    fn write(&self, name: &str, vcd: &mut String) {
+        vcd.push_str(&format!("$scope module {} $end\n", name.replace(' ', "_")));
+        for var in &self.vars {
+            vcd.push_str(var);
+        }
+        for (name, child) in &self.children {
+            child.write(name, vcd);
+        }
+        vcd.push_str("$upscope $end\n");
+    }
+}
+
+fn width(trace: &Trace) -> usize {
+    trace.nets.len().clamp(1, 64)
+}
+
+/// Declaration of a variable, written with a bit range when the name selects a slice like `out[0..7]`
+fn var(name: &str, width: usize, code: &str) -> String {
+    let reference = match name.find('[') {
+        Some(open) => {
+            let bounds = name[open + 1..name.len() - 1].replace(' ', "");
+            let mut bounds = bounds.splitn(2, "..");
+            let start = bounds.next().unwrap_or_default();
+            let range = match bounds.next() {
+                Some(end) => format!("[{}:{}]", end, start),
+                None => format!("[{}]", start),
+            };
+            format!("{} {}", &name[..open], range)
+        }
+        None => name.to_string(),
+    };
+    format!("$var wire {} {} {} $end\n", width, code, reference)
+}
+
+fn change(value: u64, width: usize, code: &str) -> String {
+    if width == 1 {
+        format!("{}{}", value, code)
+    } else {
+        format!("b{:b} {}", value, code)
+    }
+}
+
+/// A short identifier code made of printable characters
+fn identifier(mut index: usize) -> String {
+    let mut code = String::new();
+    loop {
+        code.push((b'!' + (index % 94) as u8) as char);
+        index /= 94;
+        if index == 0 {
+            return code;
+        }
+        index -= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::waveform::identifier;
+    use crate::{Project, Simulator};
+
+    #[test]
+    fn records_changes() {
+        let project = Project::new();
+        let mut bit = Simulator::new(project.chip("Bit").unwrap(), &project).unwrap();
+        bit.record(&["in", "load", "out", "Bit/Mux/out"]).unwrap();
+        bit.set("in", 1).unwrap();
+        bit.set("load", 1).unwrap();
+        bit.eval();
+        bit.tick();
+        bit.tock();
+        bit.set("load", 0).unwrap();
+        bit.step();
+
+        let waveform = bit.waveform().unwrap();
+        assert_eq!(waveform.len(), 6);
+        assert_eq!(waveform.values("out").unwrap(), vec![0, 0, 0, 1, 1, 1]);
+        assert_eq!(waveform.values("load").unwrap(), vec![0, 1, 1, 1, 0, 0]);
+        assert_eq!(waveform.values("in[0]"), None);
+
+        let vcd = waveform.to_vcd();
+        assert!(vcd.contains(
+            "$scope module Bit $end\n\
+             $var wire 1 ! clock $end\n\
+             $var wire 1 \" in $end\n\
+             $var wire 1 # load $end\n\
+             $var wire 1 $ out $end\n\
+             $scope module Mux $end\n\
+             $var wire 1 % out $end\n\
+             $upscope $end\n\
+             $upscope $end\n\
+             $enddefinitions $end\n\
+             #0\n$dumpvars\n0!\n0\"\n0#\n0$\n0%\n$end\n\
+             #1\n1\"\n1#\n1%\n#2\n1!\n#3\n0!\n1$\n"
+        ));
+        assert!(vcd.ends_with("#6\n"));
+    }
+
+    #[test]
+    fn groups_buses() {
+        let project = Project::new();
+        let mut adder = Simulator::new(project.chip("Add16").unwrap(), &project).unwrap();
+        adder.record(&["a", "out[8..15]"]).unwrap();
+        adder.set("a", 0x1234).unwrap();
+        adder.eval();
+        let vcd = adder.waveform().unwrap().to_vcd();
+        assert!(vcd.contains("$var wire 16 \" a $end\n$var wire 8 # out [15:8] $end\n"));
+        assert!(vcd.contains("#1\nb1001000110100 \"\nb10010 #\n"));
+        assert_eq!(identifier(93), "~");
+        assert_eq!(identifier(94), "!!");
+    }
+}